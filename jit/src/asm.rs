@@ -0,0 +1,748 @@
+use crate::{JitError, JitMem};
+use simplealloc::{CVec, SystemAllocator};
+
+/// General purpose registers, numbered as in the encodings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reg {
+    Rax, Rcx, Rdx, Rbx, Rsp, Rbp, Rsi, Rdi,
+    R8, R9, R10, R11, R12, R13, R14, R15,
+}
+
+impl Reg {
+    // Low 3 bits go in ModRM/SIB/opcode, the 4th in REX
+    fn low(self) -> u8 {
+        self as u8 & 7
+    }
+
+    fn is_extended(self) -> bool {
+        self as u8 >= 8
+    }
+}
+
+/// Memory operand `[base + index * scale + disp]`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mem {
+    base: Reg,
+    index: Option<(Reg, u8)>,
+    disp: i32,
+}
+
+impl Mem {
+    /// `[base + disp]`
+    pub fn base(base: Reg, disp: i32) -> Self {
+        Self { base, index: None, disp }
+    }
+
+    /// `[base + index * scale + disp]`, `scale` being 1, 2, 4 or 8.
+    pub fn indexed(base: Reg, index: Reg, scale: u8, disp: i32) -> Self {
+        assert!(matches!(scale, 1 | 2 | 4 | 8), "Invalid scale {}", scale);
+        assert!(index != Reg::Rsp, "rsp can't be used as an index");
+        Self { base, index: Some((index, scale)), disp }
+    }
+}
+
+/// Jump target, created unbound by `Assembler::new_label` and bound to
+/// a position with `Assembler::bind`, before or after the jumps to it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Label(u32);
+
+/// Conditions of `jcc`. `B`, `Be`, `A` and `Ae` compare unsigned values,
+/// `L`, `Le`, `G` and `Ge` signed ones.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cond {
+    O, No, B, Ae, E, Ne, Be, A,
+    S, Ns, P, Np, L, Ge, Le, G,
+}
+
+const UNBOUND: usize = usize::MAX;
+
+// Displacement to write once the labels are all bound
+struct Fixup {
+    // Position of the displacement, which ends the instruction
+    at: usize,
+    label: Label,
+    short: bool,
+}
+
+// Operand of the ModRM r/m field
+#[derive(Clone, Copy)]
+enum Rm {
+    Reg(Reg),
+    Mem(Mem),
+}
+
+// The 8 classic ALU operations share their encodings, only the
+// opcode base and the ModRM extension differ
+#[derive(Clone, Copy)]
+enum Alu {
+    Add = 0,
+    Or = 1,
+    And = 4,
+    Sub = 5,
+    Xor = 6,
+    Cmp = 7,
+}
+
+const REX_W: u8 = 0x48;
+
+fn fits_i8(value: i64) -> bool {
+    value >= i8::MIN as i64 && value <= i8::MAX as i64
+}
+
+fn fits_i32(value: i64) -> bool {
+    value >= i32::MIN as i64 && value <= i32::MAX as i64
+}
+
+/// x86-64 encoder writing into a `JitMem`. Operations work on 64-bit
+/// registers and follow the Intel operand order, destination first:
+///
+/// ```ignore
+/// let mut asm = Assembler::new(&mut mem);
+/// asm.mov_rr(Reg::Rax, Reg::Rcx);
+/// asm.add_ri(Reg::Rax, 3);
+/// asm.ret();
+/// ```
+///
+/// Encodings are the ones GNU as picks, so they can be checked against it.
+/// Jump displacements are written by `finalize`, which then makes the code
+/// executable. Until it does, `JitMem::finalize` refuses the code.
+pub struct Assembler<'a> {
+    mem: &'a mut JitMem,
+    // Offset of each label, `UNBOUND` until bound
+    labels: CVec<usize, SystemAllocator>,
+    fixups: CVec<Fixup, SystemAllocator>,
+}
+
+impl<'a> Assembler<'a> {
+    pub fn new(mem: &'a mut JitMem) -> Self {
+        Self {
+            mem,
+            labels: CVec::new(),
+            fixups: CVec::new(),
+        }
+    }
+
+    /// Offset of the next instruction in the `JitMem`.
+    pub fn offset(&self) -> usize {
+        self.mem.offset as usize
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.mem.push_instruct_byte(byte);
+    }
+
+    fn imm8(&mut self, value: i8) {
+        self.byte(value as u8);
+    }
+
+    fn imm32(&mut self, value: i32) {
+        self.mem.push_u32(value as u32);
+    }
+
+    // REX prefix for `reg` in the ModRM reg field and `rm`, `w` for 64-bit operands
+    fn rex(&mut self, w: bool, reg: u8, rm: Rm) {
+        let mut rex = if w { REX_W } else { 0x40 };
+        if reg >= 8 {
+            rex |= 0x4;
+        }
+        match rm {
+            Rm::Reg(r) => {
+                if r.is_extended() {
+                    rex |= 0x1;
+                }
+            }
+            Rm::Mem(m) => {
+                if let Some((index, _)) = m.index {
+                    if index.is_extended() {
+                        rex |= 0x2;
+                    }
+                }
+                if m.base.is_extended() {
+                    rex |= 0x1;
+                }
+            }
+        }
+        if rex != 0x40 {
+            self.byte(rex);
+        }
+    }
+
+    fn modrm(&mut self, reg: u8, rm: Rm) {
+        let reg = (reg & 7) << 3;
+        let m = match rm {
+            Rm::Reg(r) => {
+                self.byte(0xc0 | reg | r.low());
+                return;
+            }
+            Rm::Mem(m) => m,
+        };
+
+        // rbp and r13 as base have no disp-less form
+        let mode = if m.disp == 0 && m.base.low() != 5 {
+            0x00
+        } else if fits_i8(m.disp as i64) {
+            0x40
+        } else {
+            0x80
+        };
+
+        // rsp and r12 as base, and any index, need a SIB byte
+        if m.index.is_some() || m.base.low() == 4 {
+            let (index, scale) = match m.index {
+                Some((index, scale)) => (index.low(), scale.trailing_zeros() as u8),
+                None => (4, 0),
+            };
+            self.byte(mode | reg | 4);
+            self.byte(scale << 6 | index << 3 | m.base.low());
+        } else {
+            self.byte(mode | reg | m.base.low());
+        }
+
+        match mode {
+            0x40 => self.imm8(m.disp as i8),
+            0x80 => self.imm32(m.disp),
+            _ => {}
+        }
+    }
+
+    // REX.W, opcode bytes, ModRM/SIB/displacement
+    fn op(&mut self, opcode: &[u8], reg: u8, rm: Rm) {
+        self.rex(true, reg, rm);
+        for &b in opcode {
+            self.byte(b);
+        }
+        self.modrm(reg, rm);
+    }
+
+    fn alu_rr(&mut self, alu: Alu, dst: Reg, src: Reg) {
+        self.op(&[(alu as u8) << 3 | 0x01], src as u8, Rm::Reg(dst));
+    }
+
+    fn alu_rm(&mut self, alu: Alu, dst: Reg, src: Mem) {
+        self.op(&[(alu as u8) << 3 | 0x03], dst as u8, Rm::Mem(src));
+    }
+
+    fn alu_ri(&mut self, alu: Alu, dst: Reg, imm: i32) {
+        if fits_i8(imm as i64) {
+            self.op(&[0x83], alu as u8, Rm::Reg(dst));
+            self.imm8(imm as i8);
+        } else if dst == Reg::Rax {
+            // Short accumulator form
+            self.byte(REX_W);
+            self.byte((alu as u8) << 3 | 0x05);
+            self.imm32(imm);
+        } else {
+            self.op(&[0x81], alu as u8, Rm::Reg(dst));
+            self.imm32(imm);
+        }
+    }
+
+    pub fn mov_rr(&mut self, dst: Reg, src: Reg) {
+        self.op(&[0x89], src as u8, Rm::Reg(dst));
+    }
+
+    /// Load, `mov dst, [src]`.
+    pub fn mov_rm(&mut self, dst: Reg, src: Mem) {
+        self.op(&[0x8b], dst as u8, Rm::Mem(src));
+    }
+
+    /// Store, `mov [dst], src`.
+    pub fn mov_mr(&mut self, dst: Mem, src: Reg) {
+        self.op(&[0x89], src as u8, Rm::Mem(dst));
+    }
+
+    /// Sign-extended 32-bit immediate when it fits, `movabs` otherwise.
+    pub fn mov_ri(&mut self, dst: Reg, imm: i64) {
+        if fits_i32(imm) {
+            self.op(&[0xc7], 0, Rm::Reg(dst));
+            self.imm32(imm as i32);
+        } else {
+            self.rex(true, 0, Rm::Reg(dst));
+            self.byte(0xb8 | dst.low());
+            self.mem.push_u64(imm as u64);
+        }
+    }
+
+    /// `mov qword [dst], imm`, sign-extended.
+    pub fn mov_mi(&mut self, dst: Mem, imm: i32) {
+        self.op(&[0xc7], 0, Rm::Mem(dst));
+        self.imm32(imm);
+    }
+
+    pub fn lea(&mut self, dst: Reg, src: Mem) {
+        self.op(&[0x8d], dst as u8, Rm::Mem(src));
+    }
+
+    pub fn add_rr(&mut self, dst: Reg, src: Reg) {
+        self.alu_rr(Alu::Add, dst, src);
+    }
+
+    pub fn add_ri(&mut self, dst: Reg, imm: i32) {
+        self.alu_ri(Alu::Add, dst, imm);
+    }
+
+    pub fn add_rm(&mut self, dst: Reg, src: Mem) {
+        self.alu_rm(Alu::Add, dst, src);
+    }
+
+    pub fn sub_rr(&mut self, dst: Reg, src: Reg) {
+        self.alu_rr(Alu::Sub, dst, src);
+    }
+
+    pub fn sub_ri(&mut self, dst: Reg, imm: i32) {
+        self.alu_ri(Alu::Sub, dst, imm);
+    }
+
+    pub fn sub_rm(&mut self, dst: Reg, src: Mem) {
+        self.alu_rm(Alu::Sub, dst, src);
+    }
+
+    pub fn and_rr(&mut self, dst: Reg, src: Reg) {
+        self.alu_rr(Alu::And, dst, src);
+    }
+
+    pub fn and_ri(&mut self, dst: Reg, imm: i32) {
+        self.alu_ri(Alu::And, dst, imm);
+    }
+
+    pub fn or_rr(&mut self, dst: Reg, src: Reg) {
+        self.alu_rr(Alu::Or, dst, src);
+    }
+
+    pub fn or_ri(&mut self, dst: Reg, imm: i32) {
+        self.alu_ri(Alu::Or, dst, imm);
+    }
+
+    pub fn xor_rr(&mut self, dst: Reg, src: Reg) {
+        self.alu_rr(Alu::Xor, dst, src);
+    }
+
+    pub fn xor_ri(&mut self, dst: Reg, imm: i32) {
+        self.alu_ri(Alu::Xor, dst, imm);
+    }
+
+    pub fn cmp_rr(&mut self, dst: Reg, src: Reg) {
+        self.alu_rr(Alu::Cmp, dst, src);
+    }
+
+    pub fn cmp_ri(&mut self, dst: Reg, imm: i32) {
+        self.alu_ri(Alu::Cmp, dst, imm);
+    }
+
+    pub fn cmp_rm(&mut self, dst: Reg, src: Mem) {
+        self.alu_rm(Alu::Cmp, dst, src);
+    }
+
+    pub fn imul_rr(&mut self, dst: Reg, src: Reg) {
+        self.op(&[0x0f, 0xaf], dst as u8, Rm::Reg(src));
+    }
+
+    /// `dst = src * imm`
+    pub fn imul_rri(&mut self, dst: Reg, src: Reg, imm: i32) {
+        if fits_i8(imm as i64) {
+            self.op(&[0x6b], dst as u8, Rm::Reg(src));
+            self.imm8(imm as i8);
+        } else {
+            self.op(&[0x69], dst as u8, Rm::Reg(src));
+            self.imm32(imm);
+        }
+    }
+
+    /// Sign-extends rax into rdx, to set up `idiv`.
+    pub fn cqo(&mut self) {
+        self.byte(REX_W);
+        self.byte(0x99);
+    }
+
+    /// Divides rdx:rax by `src`, quotient in rax and remainder in rdx.
+    pub fn idiv(&mut self, src: Reg) {
+        self.op(&[0xf7], 7, Rm::Reg(src));
+    }
+
+    fn shift_ri(&mut self, ext: u8, dst: Reg, count: u8) {
+        if count == 1 {
+            self.op(&[0xd1], ext, Rm::Reg(dst));
+        } else {
+            self.op(&[0xc1], ext, Rm::Reg(dst));
+            self.byte(count);
+        }
+    }
+
+    pub fn shl_ri(&mut self, dst: Reg, count: u8) {
+        self.shift_ri(4, dst, count);
+    }
+
+    /// Logical shift, zeros come in.
+    pub fn shr_ri(&mut self, dst: Reg, count: u8) {
+        self.shift_ri(5, dst, count);
+    }
+
+    /// Arithmetic shift, the sign bit comes in.
+    pub fn sar_ri(&mut self, dst: Reg, count: u8) {
+        self.shift_ri(7, dst, count);
+    }
+
+    /// Shifts by `cl`.
+    pub fn shl_r(&mut self, dst: Reg) {
+        self.op(&[0xd3], 4, Rm::Reg(dst));
+    }
+
+    pub fn shr_r(&mut self, dst: Reg) {
+        self.op(&[0xd3], 5, Rm::Reg(dst));
+    }
+
+    pub fn test_rr(&mut self, dst: Reg, src: Reg) {
+        self.op(&[0x85], src as u8, Rm::Reg(dst));
+    }
+
+    pub fn test_ri(&mut self, dst: Reg, imm: i32) {
+        if dst == Reg::Rax {
+            self.byte(REX_W);
+            self.byte(0xa9);
+        } else {
+            self.op(&[0xf7], 0, Rm::Reg(dst));
+        }
+        self.imm32(imm);
+    }
+
+    pub fn push(&mut self, src: Reg) {
+        self.rex(false, 0, Rm::Reg(src));
+        self.byte(0x50 | src.low());
+    }
+
+    pub fn pop(&mut self, dst: Reg) {
+        self.rex(false, 0, Rm::Reg(dst));
+        self.byte(0x58 | dst.low());
+    }
+
+    /// Indirect call through a register.
+    pub fn call_r(&mut self, target: Reg) {
+        self.rex(false, 0, Rm::Reg(target));
+        self.byte(0xff);
+        self.modrm(2, Rm::Reg(target));
+    }
+
+    pub fn ret(&mut self) {
+        self.byte(0xc3);
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(UNBOUND);
+        Label(self.labels.len() as u32 - 1)
+    }
+
+    /// Binds `label` to the next instruction.
+    pub fn bind(&mut self, label: Label) {
+        let offset = self.offset();
+        let bound = &mut self.labels[label.0 as usize];
+        assert!(*bound == UNBOUND, "{:?} bound twice", label);
+        *bound = offset;
+    }
+
+    // Backward jumps get the short form when the target is close enough,
+    // forward ones the rel32 form unless `force_short`. Shrinking them in
+    // `finalize` would move code `set_jit_fn` already handed out
+    fn jump(&mut self, short: Option<u8>, long: &[u8], label: Label, force_short: bool) {
+        if let Some(short) = short {
+            let target = self.labels[label.0 as usize];
+            let close = target != UNBOUND && fits_i8(target as i64 - (self.offset() + 2) as i64);
+            if close || force_short {
+                self.byte(short);
+                self.fixups.push(Fixup { at: self.offset(), label, short: true });
+                self.mem.pending_fixups += 1;
+                self.byte(0);
+                return;
+            }
+        }
+
+        for &b in long {
+            self.byte(b);
+        }
+        self.fixups.push(Fixup { at: self.offset(), label, short: false });
+        self.mem.pending_fixups += 1;
+        self.imm32(0);
+    }
+
+    /// 2-byte form when `label` is already bound within rel8 range. Forward
+    /// jumps are always the 5-byte form, use `jmp_short` for close ones.
+    pub fn jmp(&mut self, label: Label) {
+        self.jump(Some(0xeb), &[0xe9], label, false);
+    }
+
+    /// Always uses the 2-byte form, `finalize` fails if the label is out of its range.
+    pub fn jmp_short(&mut self, label: Label) {
+        self.jump(Some(0xeb), &[0xe9], label, true);
+    }
+
+    /// 2-byte form when `label` is already bound within rel8 range. Forward
+    /// jumps are always the 6-byte form, use `jcc_short` for close ones.
+    pub fn jcc(&mut self, cond: Cond, label: Label) {
+        self.jump(Some(0x70 | cond as u8), &[0x0f, 0x80 | cond as u8], label, false);
+    }
+
+    /// Always uses the 2-byte form, `finalize` fails if the label is out of its range.
+    pub fn jcc_short(&mut self, cond: Cond, label: Label) {
+        self.jump(Some(0x70 | cond as u8), &[0x0f, 0x80 | cond as u8], label, true);
+    }
+
+    /// Relative call to code assembled in the same `JitMem`.
+    pub fn call(&mut self, label: Label) {
+        self.jump(None, &[0xe8], label, false);
+    }
+
+    /// Start of the function just emitted, see `JitMem::set_jit_fn`.
+    pub fn set_jit_fn(&mut self) -> *mut u8 {
+        self.mem.set_jit_fn()
+    }
+
+    /// Patches the jumps and makes the code executable. Fails, leaving the
+    /// code unprotected, if the code overflowed the `JitMem`, a jump targets
+    /// an unbound label or a short one can't reach its label.
+    pub fn finalize(self) -> Result<(), JitError> {
+        // Displacements past the overflow were never written
+        self.mem.check_capacity()?;
+        for fixup in &self.fixups {
+            let target = self.labels[fixup.label.0 as usize];
+            if target == UNBOUND {
+                return Err(JitError::UnboundLabel(fixup.label));
+            }
+
+            let size = if fixup.short { 1 } else { 4 };
+            let rel = target as i64 - (fixup.at + size) as i64;
+            if fixup.short {
+                if !fits_i8(rel) {
+                    return Err(JitError::ShortJumpOutOfRange(fixup.label));
+                }
+                self.mem.patch(fixup.at, &[rel as u8]);
+            } else {
+                self.mem.patch(fixup.at, &(rel as i32).to_le_bytes());
+            }
+        }
+        self.mem.pending_fixups -= self.fixups.len();
+        self.mem.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Reg::*;
+
+    // Instruction text, how to emit it, and the bytes GNU as gives for it
+    type Vector = (&'static str, fn(&mut Assembler), &'static [u8]);
+
+    const VECTORS: &[Vector] = &[
+        ("mov rax, rcx", |a| a.mov_rr(Rax, Rcx), &[0x48, 0x89, 0xc8]),
+        ("mov r15, rsp", |a| a.mov_rr(R15, Rsp), &[0x49, 0x89, 0xe7]),
+        ("mov rax, 3", |a| a.mov_ri(Rax, 3), &[0x48, 0xc7, 0xc0, 0x03, 0x00, 0x00, 0x00]),
+        ("mov r9, -1", |a| a.mov_ri(R9, -1), &[0x49, 0xc7, 0xc1, 0xff, 0xff, 0xff, 0xff]),
+        ("movabs rax, 0x123456789", |a| a.mov_ri(Rax, 0x1_2345_6789), &[0x48, 0xb8, 0x89, 0x67, 0x45, 0x23, 0x01, 0x00, 0x00, 0x00]),
+        ("movabs r10, 0x8000000000000000", |a| a.mov_ri(R10, i64::MIN), &[0x49, 0xba, 0, 0, 0, 0, 0, 0, 0, 0x80]),
+        ("mov rax, [rcx]", |a| a.mov_rm(Rax, Mem::base(Rcx, 0)), &[0x48, 0x8b, 0x01]),
+        ("mov rax, [rsp]", |a| a.mov_rm(Rax, Mem::base(Rsp, 0)), &[0x48, 0x8b, 0x04, 0x24]),
+        ("mov rax, [rbp]", |a| a.mov_rm(Rax, Mem::base(Rbp, 0)), &[0x48, 0x8b, 0x45, 0x00]),
+        ("mov rax, [r12]", |a| a.mov_rm(Rax, Mem::base(R12, 0)), &[0x49, 0x8b, 0x04, 0x24]),
+        ("mov rax, [r13]", |a| a.mov_rm(Rax, Mem::base(R13, 0)), &[0x49, 0x8b, 0x45, 0x00]),
+        ("mov r8, [rdx+8]", |a| a.mov_rm(R8, Mem::base(Rdx, 8)), &[0x4c, 0x8b, 0x42, 0x08]),
+        ("mov rax, [rcx-128]", |a| a.mov_rm(Rax, Mem::base(Rcx, -128)), &[0x48, 0x8b, 0x41, 0x80]),
+        ("mov rax, [rcx+128]", |a| a.mov_rm(Rax, Mem::base(Rcx, 128)), &[0x48, 0x8b, 0x81, 0x80, 0x00, 0x00, 0x00]),
+        ("mov rax, [rsp+0x1000]", |a| a.mov_rm(Rax, Mem::base(Rsp, 0x1000)), &[0x48, 0x8b, 0x84, 0x24, 0x00, 0x10, 0x00, 0x00]),
+        ("mov rax, [rbx+rcx*8]", |a| a.mov_rm(Rax, Mem::indexed(Rbx, Rcx, 8, 0)), &[0x48, 0x8b, 0x04, 0xcb]),
+        ("mov rax, [rbp+r9*4+16]", |a| a.mov_rm(Rax, Mem::indexed(Rbp, R9, 4, 16)), &[0x4a, 0x8b, 0x44, 0x8d, 0x10]),
+        ("mov r11, [r13+r14*2]", |a| a.mov_rm(R11, Mem::indexed(R13, R14, 2, 0)), &[0x4f, 0x8b, 0x5c, 0x75, 0x00]),
+        ("mov [rdi+24], rsi", |a| a.mov_mr(Mem::base(Rdi, 24), Rsi), &[0x48, 0x89, 0x77, 0x18]),
+        ("mov [r12+rax*1], r15", |a| a.mov_mr(Mem::indexed(R12, Rax, 1, 0), R15), &[0x4d, 0x89, 0x3c, 0x04]),
+        ("mov qword ptr [rsp+8], 42", |a| a.mov_mi(Mem::base(Rsp, 8), 42), &[0x48, 0xc7, 0x44, 0x24, 0x08, 0x2a, 0x00, 0x00, 0x00]),
+        ("lea rax, [rdi+rsi*4+12]", |a| a.lea(Rax, Mem::indexed(Rdi, Rsi, 4, 12)), &[0x48, 0x8d, 0x44, 0xb7, 0x0c]),
+        ("lea r8, [rbp-8]", |a| a.lea(R8, Mem::base(Rbp, -8)), &[0x4c, 0x8d, 0x45, 0xf8]),
+        ("add rax, rcx", |a| a.add_rr(Rax, Rcx), &[0x48, 0x01, 0xc8]),
+        ("add r8, r9", |a| a.add_rr(R8, R9), &[0x4d, 0x01, 0xc8]),
+        ("add rax, 3", |a| a.add_ri(Rax, 3), &[0x48, 0x83, 0xc0, 0x03]),
+        ("add rax, 1000", |a| a.add_ri(Rax, 1000), &[0x48, 0x05, 0xe8, 0x03, 0x00, 0x00]),
+        ("add rcx, 1000", |a| a.add_ri(Rcx, 1000), &[0x48, 0x81, 0xc1, 0xe8, 0x03, 0x00, 0x00]),
+        ("add rax, [rsp+16]", |a| a.add_rm(Rax, Mem::base(Rsp, 16)), &[0x48, 0x03, 0x44, 0x24, 0x10]),
+        ("sub rsp, 40", |a| a.sub_ri(Rsp, 40), &[0x48, 0x83, 0xec, 0x28]),
+        ("sub rdx, rbx", |a| a.sub_rr(Rdx, Rbx), &[0x48, 0x29, 0xda]),
+        ("sub r10, [r11]", |a| a.sub_rm(R10, Mem::base(R11, 0)), &[0x4d, 0x2b, 0x13]),
+        ("and rax, -16", |a| a.and_ri(Rax, -16), &[0x48, 0x83, 0xe0, 0xf0]),
+        ("and rsi, rdi", |a| a.and_rr(Rsi, Rdi), &[0x48, 0x21, 0xfe]),
+        ("or rcx, 0x100", |a| a.or_ri(Rcx, 0x100), &[0x48, 0x81, 0xc9, 0x00, 0x01, 0x00, 0x00]),
+        ("or rax, rdx", |a| a.or_rr(Rax, Rdx), &[0x48, 0x09, 0xd0]),
+        ("xor rax, rax", |a| a.xor_rr(Rax, Rax), &[0x48, 0x31, 0xc0]),
+        ("xor r12, 0x7fffffff", |a| a.xor_ri(R12, 0x7fff_ffff), &[0x49, 0x81, 0xf4, 0xff, 0xff, 0xff, 0x7f]),
+        ("cmp rax, rcx", |a| a.cmp_rr(Rax, Rcx), &[0x48, 0x39, 0xc8]),
+        ("cmp rdi, 0", |a| a.cmp_ri(Rdi, 0), &[0x48, 0x83, 0xff, 0x00]),
+        ("cmp rax, 0x12345", |a| a.cmp_ri(Rax, 0x12345), &[0x48, 0x3d, 0x45, 0x23, 0x01, 0x00]),
+        ("cmp rbx, [rbx+rbx*1]", |a| a.cmp_rm(Rbx, Mem::indexed(Rbx, Rbx, 1, 0)), &[0x48, 0x3b, 0x1c, 0x1b]),
+        ("imul rax, rcx", |a| a.imul_rr(Rax, Rcx), &[0x48, 0x0f, 0xaf, 0xc1]),
+        ("imul r9, r15", |a| a.imul_rr(R9, R15), &[0x4d, 0x0f, 0xaf, 0xcf]),
+        ("imul rax, rdx, 10", |a| a.imul_rri(Rax, Rdx, 10), &[0x48, 0x6b, 0xc2, 0x0a]),
+        ("imul rcx, rcx, 1000", |a| a.imul_rri(Rcx, Rcx, 1000), &[0x48, 0x69, 0xc9, 0xe8, 0x03, 0x00, 0x00]),
+        ("cqo", |a| a.cqo(), &[0x48, 0x99]),
+        ("idiv rcx", |a| a.idiv(Rcx), &[0x48, 0xf7, 0xf9]),
+        ("idiv r8", |a| a.idiv(R8), &[0x49, 0xf7, 0xf8]),
+        ("shl rax, 1", |a| a.shl_ri(Rax, 1), &[0x48, 0xd1, 0xe0]),
+        ("shl rax, 4", |a| a.shl_ri(Rax, 4), &[0x48, 0xc1, 0xe0, 0x04]),
+        ("shr r11, 63", |a| a.shr_ri(R11, 63), &[0x49, 0xc1, 0xeb, 0x3f]),
+        ("sar rdx, 2", |a| a.sar_ri(Rdx, 2), &[0x48, 0xc1, 0xfa, 0x02]),
+        ("shl rdx, cl", |a| a.shl_r(Rdx), &[0x48, 0xd3, 0xe2]),
+        ("shr r14, cl", |a| a.shr_r(R14), &[0x49, 0xd3, 0xee]),
+        ("test rax, rax", |a| a.test_rr(Rax, Rax), &[0x48, 0x85, 0xc0]),
+        ("test rsi, r8", |a| a.test_rr(Rsi, R8), &[0x4c, 0x85, 0xc6]),
+        ("test rax, 1", |a| a.test_ri(Rax, 1), &[0x48, 0xa9, 0x01, 0x00, 0x00, 0x00]),
+        ("test rbx, 0x80", |a| a.test_ri(Rbx, 0x80), &[0x48, 0xf7, 0xc3, 0x80, 0x00, 0x00, 0x00]),
+        ("push rbp", |a| a.push(Rbp), &[0x55]),
+        ("push r12", |a| a.push(R12), &[0x41, 0x54]),
+        ("pop rbx", |a| a.pop(Rbx), &[0x5b]),
+        ("pop r15", |a| a.pop(R15), &[0x41, 0x5f]),
+        ("call rax", |a| a.call_r(Rax), &[0xff, 0xd0]),
+        ("call r11", |a| a.call_r(R11), &[0x41, 0xff, 0xd3]),
+        ("ret", |a| a.ret(), &[0xc3]),
+    ];
+
+    #[test]
+    fn test_encoding_vectors() {
+        for &(text, emit, expected) in VECTORS {
+            let mut mem = JitMem::new();
+            let mut asm = Assembler::new(&mut mem);
+            emit(&mut asm);
+            let len = asm.offset();
+            assert_eq!(code(&mem, len), expected, "{}", text);
+        }
+    }
+
+    fn code(mem: &JitMem, len: usize) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(mem.addr, len) }
+    }
+
+    #[test]
+    fn test_assembled_fn() {
+        // (a + b) * 3 / c, with the Win64 argument registers
+        let mut mem = JitMem::new();
+        let mut asm = Assembler::new(&mut mem);
+        asm.push(Rbx);
+        asm.mov_rr(Rbx, R8);
+        asm.lea(Rax, Mem::indexed(Rcx, Rdx, 1, 0));
+        asm.imul_rri(Rax, Rax, 3);
+        asm.cqo();
+        asm.idiv(Rbx);
+        asm.pop(Rbx);
+        asm.ret();
+        let func = asm.set_jit_fn();
+        asm.finalize().unwrap();
+        let res = unsafe { core::mem::transmute::<*mut u8, extern "win64" fn(i64, i64, i64) -> i64>(func)(4, 10, 7) };
+        assert_eq!(res, 6);
+    }
+
+    #[test]
+    fn test_jump_encoding() {
+        let mut mem = JitMem::new();
+        let mut asm = Assembler::new(&mut mem);
+        let top = asm.new_label();
+        let end = asm.new_label();
+        asm.bind(top);
+        asm.ret();
+        asm.jcc(Cond::Ne, top);
+        asm.jmp(end);
+        asm.jcc_short(Cond::E, end);
+        asm.call(end);
+        asm.bind(end);
+        asm.ret();
+        let patched: &[u8] = &[
+            0xc3,
+            0x75, 0xfd, // jne top
+            0xe9, 0x07, 0x00, 0x00, 0x00, // jmp end
+            0x74, 0x05, // je end
+            0xe8, 0x00, 0x00, 0x00, 0x00, // call end
+            0xc3,
+        ];
+        let len = asm.offset();
+        asm.finalize().unwrap();
+        assert_eq!(code(&mem, len), patched);
+    }
+
+    #[test]
+    fn test_far_backward_jump() {
+        let mut mem = JitMem::new();
+        let mut asm = Assembler::new(&mut mem);
+        let top = asm.new_label();
+        asm.bind(top);
+        for _ in 0..13 {
+            asm.mov_ri(Rax, i64::MIN);
+        }
+        // 130 bytes back doesn't fit a rel8
+        asm.jcc(Cond::L, top);
+        let len = asm.offset();
+        asm.finalize().unwrap();
+        assert_eq!(&code(&mem, len)[130..], &[0x0f, 0x8c, 0x78, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_label_errors() {
+        let mut mem = JitMem::new();
+        let mut asm = Assembler::new(&mut mem);
+        let never = asm.new_label();
+        asm.jmp(never);
+        assert_eq!(asm.finalize(), Err(JitError::UnboundLabel(never)));
+
+        let mut mem = JitMem::new();
+        let mut asm = Assembler::new(&mut mem);
+        let far = asm.new_label();
+        asm.jmp_short(far);
+        for _ in 0..13 {
+            asm.mov_ri(Rax, i64::MIN);
+        }
+        asm.bind(far);
+        assert_eq!(asm.finalize(), Err(JitError::ShortJumpOutOfRange(far)));
+    }
+
+    #[test]
+    fn test_assembled_loop_memory() {
+        // Sums a slice through indexed loads: rcx = ptr, rdx = len
+        let mut mem = JitMem::new();
+        let mut asm = Assembler::new(&mut mem);
+        let top = asm.new_label();
+        let done = asm.new_label();
+        asm.xor_rr(Rax, Rax);
+        asm.test_rr(Rdx, Rdx);
+        asm.jcc(Cond::E, done);
+        asm.bind(top);
+        asm.add_rm(Rax, Mem::indexed(Rcx, Rdx, 8, -8));
+        asm.sub_ri(Rdx, 1);
+        asm.jcc(Cond::Ne, top);
+        asm.bind(done);
+        asm.ret();
+        let func = asm.set_jit_fn();
+        asm.finalize().unwrap();
+
+        let values = [1i64, 20, 300, 4000];
+        let sum = unsafe { core::mem::transmute::<*mut u8, extern "win64" fn(*const i64, usize) -> i64>(func) };
+        assert_eq!(sum(values.as_ptr(), values.len()), 4321);
+        assert_eq!(sum(values.as_ptr(), 0), 0);
+    }
+
+    #[test]
+    fn test_assembled_calls() {
+        // Clamps to 0 then doubles with a call to a helper assembled after it
+        let mut mem = JitMem::new();
+        let mut asm = Assembler::new(&mut mem);
+        let double = asm.new_label();
+        let positive = asm.new_label();
+        asm.mov_rr(Rax, Rcx);
+        asm.cmp_ri(Rax, 0);
+        asm.jcc(Cond::Ge, positive);
+        asm.xor_rr(Rax, Rax);
+        asm.bind(positive);
+        asm.call(double);
+        asm.ret();
+        let func = asm.set_jit_fn();
+        asm.bind(double);
+        asm.add_rr(Rax, Rax);
+        asm.ret();
+        asm.finalize().unwrap();
+
+        let clamp_double = unsafe { core::mem::transmute::<*mut u8, extern "win64" fn(i64) -> i64>(func) };
+        assert_eq!(clamp_double(21), 42);
+        assert_eq!(clamp_double(-5), 0);
+    }
+}
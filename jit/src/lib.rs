@@ -1,39 +1,135 @@
 #![no_std]
-use win32::{VirtualAlloc, VirtualFree, VirtualProtect};
+pub mod asm;
+
+pub use asm::{Assembler, Cond, Label, Mem, Reg};
+use simplealloc::pages::{self, PAGE_SIZE};
+use core::ffi::c_void;
+use core::fmt;
 use core::ptr;
 
-const PAGE_SIZE: usize = 4096;
-const MEM_COMMIT: u32 = 0x00001000;
-const MEM_RESERVE: u32 = 0x00002000;
-const MEM_RELEASE: u32 = 0x00008000;
-const PAGE_READWRITE: u32 = 0x04;
-const PAGE_EXECUTE_READ: u32 = 0x20;
+/// Address space `JitMem::new` reserves, pages are only committed as code is written.
+pub const DEFAULT_CAPACITY: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JitError {
+    /// A jump or call targets a label that was never bound.
+    UnboundLabel(Label),
+    /// A jump forced to the short form lands further than a rel8 reaches.
+    ShortJumpOutOfRange(Label),
+    /// The code didn't fit in the reserved `capacity`, or pages couldn't be committed.
+    CapacityExceeded { capacity: usize },
+    /// The code pages couldn't be made executable.
+    ProtectFailed,
+    /// An `Assembler` was dropped before `finalize` patched its jumps.
+    UnpatchedJumps { count: usize },
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            JitError::UnboundLabel(label) => write!(f, "{:?} is used but never bound", label),
+            JitError::ShortJumpOutOfRange(label) => write!(f, "short jump to {:?} is out of range", label),
+            JitError::CapacityExceeded { capacity } => write!(f, "code exceeds the {} bytes reserved", capacity),
+            JitError::ProtectFailed => f.write_str("code pages can't be made executable"),
+            JitError::UnpatchedJumps { count } => write!(f, "{} jumps were never patched", count),
+        }
+    }
+}
 
+/// Code buffer reserving its whole capacity up front and committing pages as
+/// the code grows, so emitted code never moves. Writes past the capacity are
+/// dropped and `finalize` reports the overflow, which lets code generation run
+/// to the end without checking every push. Once finalized the code is
+/// read-only and pushing more panics.
 pub struct JitMem {
     addr: *mut u8,
-    size: usize,
+    capacity: usize,
+    committed: usize,
     offset: isize,
-    fn_offset: isize
+    fn_offset: isize,
+    overflowed: bool,
+    finalized: bool,
+    // Jumps emitted by an `Assembler` and not patched yet
+    pending_fixups: usize,
 }
 
 impl JitMem {
     pub fn new() -> Self {
-        let buf = unsafe { VirtualAlloc(ptr::null_mut(), PAGE_SIZE, MEM_RESERVE | MEM_COMMIT, PAGE_READWRITE) };
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Reserves `capacity` bytes, rounded up to whole pages.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = pages::page_align(capacity);
+        let buf = unsafe { pages::reserve(capacity).expect("Allocation error") };
 
         Self {
             addr: buf as *mut u8,
-            size: PAGE_SIZE,
+            capacity,
+            committed: 0,
             offset: 0,
-            fn_offset: 0
+            fn_offset: 0,
+            overflowed: false,
+            finalized: false,
+            pending_fixups: 0,
         }
     }
 
-    pub fn finalize(&self) {
-        let mut old: win32::DWORD = 0;
-        unsafe { VirtualProtect(self.addr as win32::LPVOID, PAGE_SIZE, PAGE_EXECUTE_READ, &mut old) };
+    /// Number of bytes written.
+    pub fn len(&self) -> usize {
+        self.offset as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn check_capacity(&self) -> Result<(), JitError> {
+        if self.overflowed {
+            Err(JitError::CapacityExceeded { capacity: self.capacity })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Makes the written code executable, read-only from then on.
+    pub fn finalize(&mut self) -> Result<(), JitError> {
+        self.check_capacity()?;
+        if self.pending_fixups > 0 {
+            return Err(JitError::UnpatchedJumps { count: self.pending_fixups });
+        }
+        if self.committed > 0 && !unsafe { pages::protect_exec(self.addr as *mut c_void, self.committed) } {
+            return Err(JitError::ProtectFailed);
+        }
+        self.finalized = true;
+        Ok(())
+    }
+
+    // Commits the page holding `offset`, false when out of the reservation
+    fn grow(&mut self) -> bool {
+        if self.committed == self.capacity {
+            return false;
+        }
+        let ok = unsafe { pages::commit(self.addr.add(self.committed) as *mut c_void, PAGE_SIZE) };
+        if ok {
+            self.committed += PAGE_SIZE;
+        }
+        ok
     }
 
     pub fn push_instruct_byte(&mut self, byte: u8) {
+        assert!(!self.finalized, "Push to a finalized JitMem");
+        if self.overflowed {
+            return;
+        }
+        if self.offset as usize == self.committed && !self.grow() {
+            self.overflowed = true;
+            return;
+        }
         unsafe { self.addr.offset(self.offset).write(byte) };
         self.offset += 1;
     }
@@ -59,17 +155,31 @@ impl JitMem {
         }
     }
 
+    // Overwrites already emitted bytes, to patch jump displacements
+    fn patch(&mut self, offset: usize, bytes: &[u8]) {
+        assert!(!self.finalized, "Patch of a finalized JitMem");
+        assert!(offset + bytes.len() <= self.offset as usize, "Patch past the emitted code");
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), self.addr.add(offset), bytes.len()) };
+    }
+
+    /// Start of the function just emitted, the next one starts after it.
     pub fn set_jit_fn(&mut self) -> *mut u8 {
         let res = unsafe { self.addr.offset(self.fn_offset) };
-        self.fn_offset += self.offset;
+        self.fn_offset = self.offset;
         res
     }
 }
 
+impl Default for JitMem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Drop for JitMem {
     fn drop(&mut self) {
         unsafe {
-            VirtualFree(self.addr as win32::LPVOID, 0, MEM_RELEASE);
+            pages::release(self.addr as *mut c_void, self.capacity);
         }
     }
 }
@@ -91,8 +201,8 @@ mod tests {
         asmbuf.push_instruct_byte(0x00);
         asmbuf.push_instruct_byte(0x00);
         asmbuf.push_instruct_byte(0xc3);
-        asmbuf.finalize(); //asm: mov rax, 0x03; ret
-        let res = unsafe { core::mem::transmute::<_, fn() -> i32>(asmbuf.addr)() };
+        asmbuf.finalize().unwrap(); //asm: mov rax, 0x03; ret
+        let res = unsafe { core::mem::transmute::<*mut u8, fn() -> i32>(asmbuf.addr)() };
         assert_eq!(res, 3);
     }
 
@@ -106,8 +216,8 @@ mod tests {
         assert_eq!(asmbuf.offset, 3);
         asmbuf.push_instruct_byte(0xc3);
         let fn_addr = asmbuf.set_jit_fn();
-        asmbuf.finalize(); //asm: mov rax, rcx; ret
-        let res = unsafe { core::mem::transmute::<_, fn(i32) -> i32>(fn_addr)(3) };
+        asmbuf.finalize().unwrap(); //asm: mov rax, rcx; ret
+        let res = unsafe { core::mem::transmute::<*mut u8, extern "win64" fn(i32) -> i32>(fn_addr)(3) };
         assert_eq!(res, 3);
     }
 
@@ -131,13 +241,32 @@ mod tests {
         asmbuf.push_instruct_byte(0xc8);
         asmbuf.push_instruct_byte(0xc3);
         let fn2 = asmbuf.set_jit_fn();
-        asmbuf.finalize(); //asm: mov rax, 0x03; ret
-        let res1 = unsafe { core::mem::transmute::<_, fn() -> i32>(fn1)() };
+        asmbuf.finalize().unwrap(); //asm: mov rax, 0x03; ret
+        let res1 = unsafe { core::mem::transmute::<*mut u8, fn() -> i32>(fn1)() };
         assert_eq!(res1, 3);
-        let res2 = unsafe { core::mem::transmute::<_, fn(i32) -> i32>(fn2)(5) };
+        let res2 = unsafe { core::mem::transmute::<*mut u8, extern "win64" fn(i32) -> i32>(fn2)(5) };
         assert_eq!(res2, 5);
     }
 
+    #[test]
+    fn jitmem_3_fn() {
+        let mut asmbuf = JitMem::new();
+        let mut asm = Assembler::new(&mut asmbuf);
+        asm.mov_ri(Reg::Rax, 1);
+        asm.ret();
+        let fn1 = asm.set_jit_fn();
+        asm.mov_ri(Reg::Rax, 2);
+        asm.add_ri(Reg::Rax, 20);
+        asm.ret();
+        let fn2 = asm.set_jit_fn();
+        asm.mov_ri(Reg::Rax, 3);
+        asm.ret();
+        let fn3 = asm.set_jit_fn();
+        asm.finalize().unwrap();
+        let call = |f: *mut u8| unsafe { core::mem::transmute::<*mut u8, extern "win64" fn() -> i64>(f)() };
+        assert_eq!((call(fn1), call(fn2), call(fn3)), (1, 22, 3));
+    }
+
     pub extern "sysv64" fn test_from_jit() -> i32 {
         3+2
     }
@@ -145,15 +274,77 @@ mod tests {
     #[test]
     fn jitmem_rust_fn() {
         let mut asmbuf = JitMem::new();
-        let fn_addr: u64 = unsafe { core::mem::transmute(test_from_jit as extern "sysv64" fn() -> i32) };
+        let fn_addr = test_from_jit as extern "sysv64" fn() -> i32 as usize as u64;
         asmbuf.push_instruct_byte(0x48);
         asmbuf.push_instruct_byte(0xb8);
         asmbuf.push_u64(fn_addr);
         asmbuf.push_instruct_byte(0xff);
         asmbuf.push_instruct_byte(0xd0);
         asmbuf.push_instruct_byte(0xc3);
-        asmbuf.finalize();
-        let res = unsafe { core::mem::transmute::<_, fn() -> i32>(asmbuf.set_jit_fn())() };
+        asmbuf.finalize().unwrap();
+        let res = unsafe { core::mem::transmute::<*mut u8, fn() -> i32>(asmbuf.set_jit_fn())() };
         assert_eq!(res, 5);
     }
+
+    #[test]
+    fn jitmem_unpatched_jumps() {
+        let mut asmbuf = JitMem::new();
+        {
+            let mut asm = Assembler::new(&mut asmbuf);
+            let end = asm.new_label();
+            asm.jmp(end);
+            asm.call(end);
+            asm.bind(end);
+            asm.ret();
+        }
+        assert_eq!(asmbuf.finalize(), Err(JitError::UnpatchedJumps { count: 2 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Push to a finalized JitMem")]
+    fn jitmem_push_after_finalize() {
+        let mut asmbuf = JitMem::new();
+        asmbuf.push_instruct_byte(0xc3);
+        asmbuf.finalize().unwrap();
+        asmbuf.push_instruct_byte(0xc3);
+    }
+
+    #[test]
+    fn jitmem_grow() {
+        // Over 3 pages of `add rax, 1`
+        let mut asmbuf = JitMem::new();
+        assert_eq!(asmbuf.capacity(), DEFAULT_CAPACITY);
+        let mut asm = Assembler::new(&mut asmbuf);
+        asm.xor_rr(Reg::Rax, Reg::Rax);
+        for _ in 0..3000 {
+            asm.add_ri(Reg::Rax, 1);
+        }
+        asm.ret();
+        let func = asm.set_jit_fn();
+        asm.finalize().unwrap();
+        assert_eq!(asmbuf.len(), 3 + 3000 * 4 + 1);
+        let res = unsafe { core::mem::transmute::<*mut u8, extern "win64" fn() -> i64>(func)() };
+        assert_eq!(res, 3000);
+    }
+
+    #[test]
+    fn jitmem_overflow() {
+        let mut asmbuf = JitMem::with_capacity(100);
+        assert_eq!(asmbuf.capacity(), PAGE_SIZE);
+        for _ in 0..PAGE_SIZE + 10 {
+            asmbuf.push_instruct_byte(0x90);
+        }
+        assert_eq!(asmbuf.len(), PAGE_SIZE);
+        assert_eq!(asmbuf.finalize(), Err(JitError::CapacityExceeded { capacity: PAGE_SIZE }));
+
+        // Jumps whose displacement was dropped aren't patched
+        let mut asmbuf = JitMem::with_capacity(PAGE_SIZE);
+        let mut asm = Assembler::new(&mut asmbuf);
+        let end = asm.new_label();
+        for _ in 0..PAGE_SIZE / 5 + 1 {
+            asm.jmp(end);
+        }
+        asm.bind(end);
+        assert_eq!(asm.finalize(), Err(JitError::CapacityExceeded { capacity: PAGE_SIZE }));
+    }
 }
@@ -1,7 +1,15 @@
-use core::mem::{size_of, align_of};
+use core::mem::{size_of, align_of, size_of_val, align_of_val};
+use core::cmp::min;
 use core::ffi::c_void;
-use win32::{HANDLE, GetProcessHeap, HeapAlloc, HeapFree};
+use core::ptr;
+#[cfg(windows)]
+use win32::{HANDLE, GetProcessHeap, HeapAlloc, HeapFree, HeapReAlloc};
 
+// Alignment guaranteed by HeapAlloc (MEMORY_ALLOCATION_ALIGNMENT)
+#[cfg(windows)]
+const HEAP_ALIGN: usize = 2 * size_of::<usize>();
+
+#[derive(Clone, Copy)]
 pub struct Layout
 {
     pub size: usize,
@@ -10,12 +18,13 @@ pub struct Layout
 
 impl Layout
 {
-    pub fn new(size: usize) -> Self
+    pub fn new(size: usize, align: usize) -> Self
     {
+        debug_assert!(align.is_power_of_two(), "Alignment must be a power of two");
         Self
         {
             size,
-            align: 4,
+            align,
         }
     }
 
@@ -36,19 +45,89 @@ impl Layout
             align: align_of::<T>(),
         }
     }
+
+    pub fn for_value<T: ?Sized>(value: &T) -> Self
+    {
+        Self
+        {
+            size: size_of_val(value),
+            align: align_of_val(value),
+        }
+    }
 }
 
 pub trait Allocator
 {
+    /// Returns a block of at least `layout.size` bytes aligned to `layout.align`.
+    ///
+    /// # Safety
+    /// The block is uninitialized and must only be freed through this allocator.
     unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void>;
-    unsafe fn dealloc(&mut self, ptr: *mut c_void);
+
+    /// # Safety
+    /// `ptr` must come from this allocator and `layout` must be the one the
+    /// block was allocated (or last reallocated) with.
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout);
+
+    /// Resizes a block previously allocated with `layout`, keeping its content.
+    /// On failure the old block is left untouched.
+    /// The default falls back to alloc + copy + dealloc, allocators that can
+    /// grow or shrink in place should override it.
+    ///
+    /// # Safety
+    /// Same requirements as `dealloc`. On success `ptr` must not be used anymore.
+    unsafe fn realloc(&mut self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void>
+    {
+        realloc_by_copy(self, ptr, layout, new_size)
+    }
+}
+
+/// Allocates a new block, copies the content and frees the old one.
+///
+/// # Safety
+/// Same requirements as `Allocator::realloc`.
+pub unsafe fn realloc_by_copy<A: Allocator + ?Sized>(alloc: &mut A, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void>
+{
+    let new_ptr = alloc.alloc(Layout::new(new_size, layout.align))?;
+    ptr::copy_nonoverlapping(ptr as *const u8, new_ptr as *mut u8, min(layout.size, new_size));
+    alloc.dealloc(ptr, layout);
+    Some(new_ptr)
+}
+
+// Helpers for backends whose natural alignment is lower than requested:
+// over-allocate by `align` bytes, align the returned pointer and store the
+// original one in the word just before it.
+pub(crate) fn over_aligned_size(layout: Layout) -> usize
+{
+    layout.size + layout.align
 }
 
+pub(crate) unsafe fn align_block(raw: *mut c_void, align: usize) -> *mut c_void
+{
+    // `raw` is at least word aligned and `align` is bigger than its alignment,
+    // so there is always room for the header.
+    let aligned = ((raw as usize + align) & !(align - 1)) as *mut c_void;
+    (aligned as *mut usize).sub(1).write(raw as usize);
+    aligned
+}
+
+pub(crate) unsafe fn unalign_block(ptr: *mut c_void) -> *mut c_void
+{
+    (ptr as *mut usize).sub(1).read() as *mut c_void
+}
+
+#[cfg(windows)]
+#[derive(Clone)]
 pub struct Win32HeapAllocator
 {
     heap: HANDLE,
 }
 
+// The process heap serializes access itself, the handle can move between threads
+#[cfg(windows)]
+unsafe impl Send for Win32HeapAllocator {}
+
+#[cfg(windows)]
 impl Default for Win32HeapAllocator {
     fn default() -> Self
     {
@@ -59,23 +138,153 @@ impl Default for Win32HeapAllocator {
     }
 }
 
+#[cfg(windows)]
 impl Allocator for Win32HeapAllocator {
     unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void>
     {
-        let ptr = HeapAlloc(self.heap, 0, layout.size);
+        let over_aligned = layout.align > HEAP_ALIGN;
+        let size = if over_aligned { over_aligned_size(layout) } else { layout.size };
+        let ptr = HeapAlloc(self.heap, 0, size);
 
         if ptr.is_null()
         {
             None
         }
+        else if over_aligned
+        {
+            Some(align_block(ptr as *mut c_void, layout.align))
+        }
         else
         {
             Some(ptr as *mut c_void)
         }
     }
 
-    unsafe fn dealloc(&mut self, ptr: *mut c_void)
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout)
     {
+        let ptr = if layout.align > HEAP_ALIGN { unalign_block(ptr) } else { ptr };
         HeapFree(self.heap, 0, ptr as win32::LPVOID);
     }
+
+    unsafe fn realloc(&mut self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void>
+    {
+        // HeapReAlloc could move the block to an address with a different
+        // alignment offset, so over-aligned blocks are copied by hand.
+        if layout.align > HEAP_ALIGN
+        {
+            return realloc_by_copy(self, ptr, layout, new_size);
+        }
+
+        let new_ptr = HeapReAlloc(self.heap, 0, ptr as win32::LPVOID, new_size);
+
+        if new_ptr.is_null()
+        {
+            None
+        }
+        else
+        {
+            Some(new_ptr as *mut c_void)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::SystemAllocator;
+
+    // Uses the default realloc
+    #[derive(Default)]
+    struct CopyingAllocator(SystemAllocator);
+
+    impl Allocator for CopyingAllocator {
+        unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+            self.0.alloc(layout)
+        }
+
+        unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout) {
+            self.0.dealloc(ptr, layout)
+        }
+    }
+
+    unsafe fn check_realloc<A: Allocator>(alloc: &mut A) {
+        let layout = Layout::from_array_type::<u32>(16);
+        let ptr = alloc.alloc(layout).unwrap() as *mut u32;
+        for i in 0..16 {
+            ptr.add(i).write(i as u32);
+        }
+
+        let ptr = alloc.realloc(ptr as *mut c_void, layout, layout.size * 64).unwrap() as *mut u32;
+        for i in 0..16 {
+            assert_eq!(ptr.add(i).read(), i as u32);
+        }
+
+        let ptr = alloc.realloc(ptr as *mut c_void, Layout::from_array_type::<u32>(16 * 64), 4 * 8).unwrap() as *mut u32;
+        for i in 0..8 {
+            assert_eq!(ptr.add(i).read(), i as u32);
+        }
+        alloc.dealloc(ptr as *mut c_void, Layout::from_array_type::<u32>(8));
+    }
+
+    #[test]
+    fn test_realloc() {
+        unsafe {
+            check_realloc(&mut SystemAllocator::default());
+            check_realloc(&mut CopyingAllocator(SystemAllocator::default()));
+        }
+    }
+
+    fn is_aligned(addr: usize, align: usize) -> bool {
+        addr & (align - 1) == 0
+    }
+
+    macro_rules! check_alignments {
+        ($($align:literal => $name:ident),*) => {
+            $(
+                #[repr(align($align))]
+                struct $name(u8);
+            )*
+
+            unsafe fn check_alignments<A: Allocator + Default>(alloc: &mut A) {
+                $(
+                    let layout = Layout::from_array_type::<$name>(3);
+                    assert_eq!(layout.align, $align);
+                    let ptr = alloc.alloc(layout).unwrap();
+                    assert!(is_aligned(ptr as usize, $align));
+                    let items = ptr as *mut $name;
+                    for i in 0..3 {
+                        items.add(i).write($name(i as u8));
+                    }
+                    let ptr = alloc.realloc(ptr, layout, layout.size * 5).unwrap();
+                    assert!(is_aligned(ptr as usize, $align));
+                    let items = ptr as *mut $name;
+                    for i in 0..3 {
+                        assert_eq!((*items.add(i)).0, i as u8);
+                    }
+                    alloc.dealloc(ptr, Layout::from_array_type::<$name>(15));
+
+                    let mut cvec: crate::vec::CVec<$name, A> = crate::vec::CVec::new();
+                    for i in 0..10 {
+                        cvec.push($name(i));
+                        assert!(is_aligned(cvec.as_ptr() as usize, $align));
+                    }
+                    assert_eq!(cvec[9].0, 9);
+                )*
+            }
+        };
+    }
+
+    check_alignments!(
+        1 => Align1, 2 => Align2, 4 => Align4, 8 => Align8, 16 => Align16, 32 => Align32,
+        64 => Align64, 128 => Align128, 256 => Align256, 512 => Align512, 1024 => Align1024,
+        2048 => Align2048, 4096 => Align4096
+    );
+
+    #[test]
+    fn test_alignment() {
+        unsafe {
+            check_alignments(&mut SystemAllocator::default());
+            check_alignments(&mut CopyingAllocator(SystemAllocator::default()));
+        }
+    }
 }
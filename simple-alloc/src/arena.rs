@@ -0,0 +1,213 @@
+use crate::alloc::*;
+use core::cell::Cell;
+use core::ffi::c_void;
+use core::ptr;
+
+const ARENA_ALIGN: usize = 16;
+const NO_LAST: usize = usize::MAX;
+
+/// Bump allocator over a single block reserved up front.
+/// Allocations go through `ArenaHandle`s and are only given back all at once
+/// with `reset` or `reset_to`, which is what per-frame scratch memory wants.
+pub struct ArenaAllocator<A: Allocator> {
+    base: *mut u8,
+    capacity: usize,
+    offset: Cell<usize>,
+    // Start of the most recent allocation, which can still be grown or freed in place
+    last: Cell<usize>,
+    alloc: A,
+}
+
+/// Position in an arena to go back to with `ArenaAllocator::reset_to`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ArenaMark(usize);
+
+impl<A: Allocator + Default> ArenaAllocator<A> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, A::default())
+    }
+}
+
+impl<A: Allocator> ArenaAllocator<A> {
+    pub fn with_capacity_in(capacity: usize, mut alloc: A) -> Self {
+        let base = unsafe {
+            alloc.alloc(Layout::new(capacity, ARENA_ALIGN)).expect("Allocation error") as *mut u8
+        };
+
+        Self {
+            base,
+            capacity,
+            offset: Cell::new(0),
+            last: Cell::new(NO_LAST),
+            alloc,
+        }
+    }
+
+    /// Allocator to give to a `CVec`, `CString`, ... The borrow keeps the
+    /// arena from being reset while containers using it are alive.
+    pub fn handle(&self) -> ArenaHandle<'_, A> {
+        ArenaHandle { arena: self }
+    }
+
+    pub fn mark(&self) -> ArenaMark {
+        ArenaMark(self.offset.get())
+    }
+
+    /// Frees everything allocated after `mark` was taken.
+    pub fn reset_to(&mut self, mark: ArenaMark) {
+        debug_assert!(mark.0 <= self.offset.get(), "Arena mark is past the current offset");
+        if mark.0 < self.offset.get() {
+            self.offset.set(mark.0);
+            self.last.set(NO_LAST);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.reset_to(ArenaMark(0));
+    }
+
+    pub fn used(&self) -> usize {
+        self.offset.get()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.capacity - self.offset.get()
+    }
+
+    fn bump(&self, layout: Layout) -> Option<*mut c_void> {
+        let base = self.base as usize;
+        let start = ((base + self.offset.get() + layout.align - 1) & !(layout.align - 1)) - base;
+        let end = start.checked_add(layout.size)?;
+        if end > self.capacity {
+            return None;
+        }
+
+        self.offset.set(end);
+        self.last.set(start);
+        Some(unsafe { self.base.add(start) } as *mut c_void)
+    }
+
+    fn is_last(&self, ptr: *mut c_void) -> bool {
+        self.last.get() != NO_LAST && ptr as usize == self.base as usize + self.last.get()
+    }
+}
+
+impl<A: Allocator> Drop for ArenaAllocator<A> {
+    fn drop(&mut self) {
+        unsafe {
+            self.alloc.dealloc(self.base as *mut c_void, Layout::new(self.capacity, ARENA_ALIGN));
+        }
+    }
+}
+
+pub struct ArenaHandle<'a, A: Allocator> {
+    arena: &'a ArenaAllocator<A>,
+}
+
+impl<'a, A: Allocator> Clone for ArenaHandle<'a, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, A: Allocator> Copy for ArenaHandle<'a, A> {}
+
+impl<'a, A: Allocator> Allocator for ArenaHandle<'a, A> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        self.arena.bump(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, _layout: Layout) {
+        // Only the most recent allocation can be given back before a reset
+        if self.arena.is_last(ptr) {
+            self.arena.offset.set(self.arena.last.get());
+            self.arena.last.set(NO_LAST);
+        }
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void> {
+        let arena = self.arena;
+        if arena.is_last(ptr) {
+            let end = arena.last.get().checked_add(new_size)?;
+            if end <= arena.capacity {
+                arena.offset.set(end);
+                return Some(ptr);
+            }
+            return None;
+        }
+
+        let new_ptr = self.alloc(Layout::new(new_size, layout.align))?;
+        ptr::copy_nonoverlapping(ptr as *const u8, new_ptr as *mut u8, core::cmp::min(layout.size, new_size));
+        Some(new_ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::SystemAllocator;
+    use crate::string::CString;
+    use crate::vec::CVec;
+
+    type Arena = ArenaAllocator<SystemAllocator>;
+
+    #[test]
+    fn test_arena_alloc() {
+        let arena = Arena::with_capacity(256);
+        let mut handle = arena.handle();
+        unsafe {
+            let a = handle.alloc(Layout::new(3, 1)).unwrap();
+            let b = handle.alloc(Layout::new(8, 8)).unwrap();
+            assert_eq!(b as usize % 8, 0);
+            assert!(b as usize >= a as usize + 3);
+            let c = handle.alloc(Layout::new(64, 64)).unwrap();
+            assert_eq!(c as usize % 64, 0);
+            assert!(handle.alloc(Layout::new(256, 1)).is_none());
+        }
+        assert!(arena.used() <= 256);
+    }
+
+    #[test]
+    fn test_arena_grow_in_place() {
+        let arena = Arena::with_capacity(1024);
+        let mut cvec: CVec<u32, _> = CVec::new_in(arena.handle());
+        for i in 0..128 {
+            cvec.push(i);
+        }
+        // The vector was the only user so it grew in place
+        assert_eq!(arena.used(), 128 * 4);
+        assert_eq!(cvec[127], 127);
+        drop(cvec);
+        assert_eq!(arena.used(), 0);
+    }
+
+    #[test]
+    fn test_arena_mark_reset() {
+        let mut arena = Arena::with_capacity(4096);
+        {
+            let name = CString::from_str_in("uTime", arena.handle());
+            assert_eq!(name.len(), 5);
+        }
+        let mark = arena.mark();
+        {
+            let mut vertices: CVec<f32, _> = CVec::new_in(arena.handle());
+            let mut indices: CVec<u16, _> = CVec::new_in(arena.handle());
+            for i in 0..64 {
+                vertices.push(i as f32);
+                indices.push(i);
+            }
+            assert_eq!(vertices[63], 63.0);
+            assert_eq!(indices[63], 63);
+        }
+        assert!(arena.used() > mark.0);
+        arena.reset_to(mark);
+        assert_eq!(arena.mark(), mark);
+        arena.reset();
+        assert_eq!(arena.used(), 0);
+        assert_eq!(arena.remaining(), 4096);
+    }
+}
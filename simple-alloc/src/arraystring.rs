@@ -0,0 +1,326 @@
+use crate::arrayvec::{ArrayVec, CapacityError};
+use crate::string::CStr;
+use core::borrow::Borrow;
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use core::cmp::Ordering;
+use core::fmt::{self, Write};
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+use core::str;
+
+/// `CString` stored inline in `N` bytes, the terminating NUL included,
+/// so it holds up to `N - 1` bytes of UTF-8. Pushing past the capacity
+/// panics, while the conversions from C buffers keep what fits.
+pub struct ArrayString<const N: usize> {
+    // Empty, or the content followed by a NUL
+    inner: ArrayVec<u8, N>
+}
+
+impl<const N: usize> ArrayString<N> {
+    pub const fn new() -> Self {
+        assert!(N > 0, "ArrayString needs room for the NUL");
+        Self {
+            inner: ArrayVec::new()
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(src: &str) -> Self {
+        let mut res = Self::new();
+        res.push_str(src);
+        res
+    }
+
+    /// Takes `src` up to its first NUL, invalid UTF-8 sequences become U+FFFD.
+    pub fn from_u8_slice(src: &[u8]) -> Self {
+        let src = match src.iter().position(|&b| b == 0) {
+            Some(end) => &src[..end],
+            None => src,
+        };
+
+        let mut res = Self::new();
+        for chunk in src.utf8_chunks() {
+            if !res.push_str_truncated(chunk.valid()) {
+                break;
+            }
+            if !chunk.invalid().is_empty() && res.try_push(REPLACEMENT_CHARACTER).is_err() {
+                break;
+            }
+        }
+        res
+    }
+
+    /// Decodes a UTF-16 string up to its first NUL. Returns `None` on unpaired surrogates.
+    pub fn from_utf16(src: &[u16]) -> Option<Self> {
+        let mut res = Self::new();
+        for c in decode_utf16(until_nul(src).iter().copied()) {
+            if res.try_push(c.ok()?).is_err() {
+                break;
+            }
+        }
+        Some(res)
+    }
+
+    /// Same as `from_utf16` but replaces unpaired surrogates with U+FFFD.
+    pub fn from_utf16_lossy(src: &[u16]) -> Self {
+        let mut res = Self::new();
+        for c in decode_utf16(until_nul(src).iter().copied()) {
+            if res.try_push(c.unwrap_or(REPLACEMENT_CHARACTER)).is_err() {
+                break;
+            }
+        }
+        res
+    }
+
+    /// Backs the `aformat!` macro, panics if the text doesn't fit.
+    pub fn from_fmt(args: fmt::Arguments) -> Self {
+        let mut res = Self::new();
+        res.write_fmt(args).expect("ArrayString capacity exceeded");
+        res
+    }
+
+    pub fn push(&mut self, c: char) {
+        let mut buf = [0; 4];
+        self.push_str(c.encode_utf8(&mut buf));
+    }
+
+    pub fn try_push(&mut self, c: char) -> Result<(), CapacityError<char>> {
+        let mut buf = [0; 4];
+        self.try_push_str(c.encode_utf8(&mut buf)).map_err(|_| CapacityError(c))
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        if self.try_push_str(s).is_err() {
+            panic!("ArrayString capacity {} exceeded", self.capacity());
+        }
+    }
+
+    /// Pushes all of `s` or nothing.
+    pub fn try_push_str(&mut self, s: &str) -> Result<(), CapacityError> {
+        if s.is_empty() {
+            return Ok(());
+        }
+        if s.len() > self.capacity() - self.len() {
+            return Err(CapacityError(()));
+        }
+
+        // Overwrite the terminating NUL and put it back at the end
+        self.inner.pop();
+        self.inner.extend_from_slice(s.as_bytes());
+        self.inner.push(b'\0');
+        Ok(())
+    }
+
+    // Pushes the longest prefix of `s` that fits, returns whether all of it did
+    fn push_str_truncated(&mut self, s: &str) -> bool {
+        let mut end = core::cmp::min(s.len(), self.capacity() - self.len());
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.push_str(&s[..end]);
+        end == s.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Length in bytes, without the terminating NUL.
+    pub fn len(&self) -> usize {
+        self.inner.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Number of bytes it can hold, without the terminating NUL.
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.as_c_str().as_ptr() as *const u8
+    }
+
+    /// Pointer to the NUL-terminated string, for C functions taking a `const char*`.
+    pub fn as_c_ptr(&self) -> *const i8 {
+        self.as_c_str().as_ptr()
+    }
+
+    /// The string up to its first NUL, pushed `'\0'` included.
+    pub fn as_c_str(&self) -> &CStr {
+        let bytes: &[u8] = if self.inner.is_empty() { b"\0" } else { &self.inner };
+        CStr::from_bytes_until_nul(bytes).expect("ArrayString lost its NUL terminator")
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.inner[..self.len()]) }
+    }
+
+    /// Encodes the string as NUL-terminated UTF-16 for the `W` Win32 functions.
+    /// A string never has more UTF-16 units than UTF-8 bytes, so it always fits.
+    pub fn to_u16_str(&self) -> ArrayVec<u16, N> {
+        let mut u16_str = ArrayVec::new();
+        u16_str.extend(self.encode_utf16());
+        u16_str.push(0);
+        u16_str
+    }
+}
+
+impl<const N: usize> Default for ArrayString<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Deref for ArrayString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> Borrow<str> for ArrayString<N> {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> Clone for ArrayString<N> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+// Fails without writing anything when `s` doesn't fit
+impl<const N: usize> Write for ArrayString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.try_push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<const N: usize, const M: usize> PartialEq<ArrayString<M>> for ArrayString<N> {
+    fn eq(&self, other: &ArrayString<M>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for ArrayString<N> {}
+
+impl<const N: usize> PartialEq<str> for ArrayString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, const N: usize> PartialEq<&'a str> for ArrayString<N> {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<const N: usize> PartialOrd for ArrayString<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for ArrayString<N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl<const N: usize> Hash for ArrayString<N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl<const N: usize> fmt::Display for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Debug for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Same as `cformat!`, but builds an `ArrayString` on the stack:
+///
+/// ```ignore
+/// let title: ArrayString<64> = aformat!("Shader {} failed", id);
+/// ```
+#[macro_export]
+macro_rules! aformat {
+    ($($arg:tt)*) => {
+        $crate::ArrayString::from_fmt(format_args!($($arg)*))
+    };
+}
+
+fn until_nul(src: &[u16]) -> &[u16] {
+    match src.iter().position(|&c| c == 0) {
+        Some(end) => &src[..end],
+        None => src,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_array_string() {
+        let mut name: ArrayString<16> = ArrayString::from_str("glGen");
+        assert_eq!(name.capacity(), 15);
+        name.push_str("Buffers");
+        name.push('✓');
+        assert_eq!(name, "glGenBuffers✓");
+        assert_eq!(name.len(), 15);
+        assert_eq!(name.try_push('!'), Err(CapacityError('!')));
+        assert_eq!(name.as_c_str().to_bytes_with_nul().len(), 16);
+        assert_eq!(unsafe { name.as_ptr().add(name.len()).read() }, 0);
+
+        name.clear();
+        assert_eq!(name.as_c_str().to_bytes_with_nul(), b"\0");
+        assert!(write!(name, "{}x{}", 1920, 1080).is_ok());
+        assert!(write!(name, " @ {}Hz", 144).is_err());
+        assert_eq!(name, "1920x1080");
+
+        let title: ArrayString<32> = aformat!("{} {:?}", name, "fps");
+        assert_eq!(title, "1920x1080 \"fps\"");
+        // C code stops at the first NUL, so does the `CStr`
+        let split: ArrayString<16> = ArrayString::from_str("gl\0Clear");
+        assert_eq!(split.len(), 8);
+        assert_eq!(split.as_c_str().to_bytes_with_nul(), b"gl\0");
+    }
+
+    #[test]
+    fn test_array_string_conversions() {
+        let title: ArrayString<16> = ArrayString::from_str("Démo 🎵");
+        let wide = title.to_u16_str();
+        assert_eq!(wide.as_slice(), &[0x44, 0xE9, 0x6D, 0x6F, 0x20, 0xD83C, 0xDFB5, 0]);
+        assert_eq!(ArrayString::<16>::from_utf16(&wide).unwrap(), title);
+        assert!(ArrayString::<16>::from_utf16(&[0x41, 0xD800]).is_none());
+        assert_eq!(ArrayString::<8>::from_utf16_lossy(&[0x41, 0xD800, 0x41]), "A\u{FFFD}A");
+
+        // GL info logs are cut to the capacity, on a char boundary
+        let log = b"0(3) : error \xC3\xA9\xFF\0garbage";
+        assert_eq!(ArrayString::<64>::from_u8_slice(log), "0(3) : error é\u{FFFD}");
+        assert_eq!(ArrayString::<15>::from_u8_slice(log), "0(3) : error ");
+        assert_eq!(ArrayString::<4>::from_utf16(&wide).unwrap(), "Dé");
+    }
+
+    #[test]
+    #[should_panic(expected = "ArrayString capacity 3 exceeded")]
+    fn test_array_string_overflow() {
+        ArrayString::<4>::from_str("glClear");
+    }
+}
@@ -0,0 +1,379 @@
+use core::fmt;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Deref, DerefMut, Index, IndexMut};
+use core::ptr;
+use core::slice::{self, SliceIndex};
+
+/// Error of the `try_` methods of `ArrayVec` and `ArrayString`,
+/// gives back the value that didn't fit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CapacityError<T = ()>(pub T);
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Insufficient capacity")
+    }
+}
+
+/// `CVec` stored inline with a fixed capacity of `N`, never touches the heap.
+/// Pushing into a full `ArrayVec` panics, `try_push` hands the value back instead.
+pub struct ArrayVec<T, const N: usize> {
+    length: usize,
+    data: [MaybeUninit<T>; N],
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            length: 0,
+            // An array of MaybeUninit doesn't need initialization
+            data: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.try_push(value).is_err() {
+            panic!("ArrayVec capacity {} exceeded", N);
+        }
+    }
+
+    pub fn try_push(&mut self, value: T) -> Result<(), CapacityError<T>> {
+        if self.length == N {
+            return Err(CapacityError(value));
+        }
+
+        unsafe {
+            self.as_mut_ptr().add(self.length).write(value);
+        }
+        self.length += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.length == 0 {
+            None
+        } else {
+            self.length -= 1;
+            Some(unsafe { self.as_ptr().add(self.length).read() })
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.length, "Insertion index {} out of bounds (len {})", index, self.length);
+        assert!(self.length < N, "ArrayVec capacity {} exceeded", N);
+
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            ptr::copy(p, p.add(1), self.length - index);
+            p.write(value);
+        }
+        self.length += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.length, "Removal index {} out of bounds (len {})", index, self.length);
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            let value = p.read();
+            ptr::copy(p.add(1), p, self.length - index - 1);
+            self.length -= 1;
+            value
+        }
+    }
+
+    /// Removes an element in O(1) by moving the last one in its place.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.length, "Removal index {} out of bounds (len {})", index, self.length);
+        unsafe {
+            let last = self.as_ptr().add(self.length - 1).read();
+            self.length -= 1;
+            if index == self.length {
+                last
+            } else {
+                ptr::replace(self.as_mut_ptr().add(index), last)
+            }
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.length {
+            return;
+        }
+
+        // Shorten first so a panicking destructor can't cause a double drop
+        let tail = ptr::slice_from_raw_parts_mut(unsafe { self.as_mut_ptr().add(len) }, self.length - len);
+        self.length = len;
+        unsafe {
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    /// Keeps only the elements for which `f` returns true, preserving their order.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let len = self.length;
+        // Elements leak instead of being dropped twice if `f` panics
+        self.length = 0;
+        let mut deleted = 0;
+        for i in 0..len {
+            unsafe {
+                let p = self.as_mut_ptr().add(i);
+                if !f(&*p) {
+                    ptr::drop_in_place(p);
+                    deleted += 1;
+                } else if deleted > 0 {
+                    ptr::copy_nonoverlapping(p, p.sub(deleted), 1);
+                }
+            }
+        }
+        self.length = len - deleted;
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.length == N
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn remaining_capacity(&self) -> usize {
+        N - self.length
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.data.as_ptr() as *const T
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.data.as_mut_ptr() as *mut T
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.length) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.length) }
+    }
+}
+
+impl<T: Clone, const N: usize> ArrayVec<T, N> {
+    /// Panics if `other` doesn't fit, without pushing any of it.
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        assert!(other.len() <= self.remaining_capacity(), "ArrayVec capacity {} exceeded", N);
+        for value in other {
+            self.push(value.clone());
+        }
+    }
+}
+
+impl<T, const N: usize> Default for ArrayVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, const N: usize> Deref for ArrayVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const N: usize> Index<I> for ArrayVec<T, N> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const N: usize> IndexMut<I> for ArrayVec<T, N> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut res = Self::new();
+        res.extend_from_slice(self);
+        res
+    }
+}
+
+impl<T, const N: usize> Extend<T> for ArrayVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for ArrayVec<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for ArrayVec<T, N> {}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArrayVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+pub struct ArrayVecIntoIter<T, const N: usize> {
+    inner: ArrayVec<T, N>,
+    current: usize,
+    size: usize,
+}
+
+impl<T, const N: usize> Iterator for ArrayVecIntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.current >= self.size {
+            None
+        } else {
+            let index = self.current;
+            self.current += 1;
+            Some(unsafe { self.inner.as_ptr().add(index).read() })
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVecIntoIter<T, N> {
+    fn drop(&mut self) {
+        // Drop the elements we didn't iterate over
+        if mem::needs_drop::<T>() {
+            unsafe {
+                for i in self.current..self.size {
+                    ptr::drop_in_place(self.inner.as_mut_ptr().add(i));
+                }
+            }
+        }
+
+        // The inner ArrayVec must not drop anything else
+        self.inner.length = 0;
+    }
+}
+
+impl<T, const N: usize> IntoIterator for ArrayVec<T, N> {
+    type Item = T;
+    type IntoIter = ArrayVecIntoIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        ArrayVecIntoIter {
+            size: self.length,
+            inner: self,
+            current: 0,
+        }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a ArrayVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut ArrayVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_array_vec() {
+        let mut values: ArrayVec<u32, 4> = ArrayVec::new();
+        assert_eq!(values.capacity(), 4);
+        values.extend_from_slice(&[1, 2, 3]);
+        values.insert(0, 0);
+        assert!(values.is_full());
+        assert_eq!(values.try_push(4), Err(CapacityError(4)));
+        assert_eq!(values.as_slice(), &[0, 1, 2, 3]);
+
+        assert_eq!(values.swap_remove(0), 0);
+        assert_eq!(values.remove(1), 1);
+        assert_eq!(values.as_slice(), &[3, 2]);
+        values.retain(|&v| v > 2);
+        assert_eq!(values.pop(), Some(3));
+        assert_eq!(values.pop(), None);
+
+        values.extend(5..9);
+        values.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(&values[..2], &[8, 7]);
+        assert_eq!(values.clone().into_iter().sum::<u32>(), 26);
+    }
+
+    #[test]
+    #[should_panic(expected = "ArrayVec capacity 2 exceeded")]
+    fn test_array_vec_overflow() {
+        let mut values: ArrayVec<u8, 2> = ArrayVec::new();
+        values.extend(0..3);
+    }
+
+    #[test]
+    fn test_array_vec_drop() {
+        let drops = Cell::new(0);
+        {
+            let mut values: ArrayVec<DropCounter, 8> = ArrayVec::new();
+            for _ in 0..6 {
+                values.push(DropCounter(&drops));
+            }
+            values.truncate(4);
+            assert_eq!(drops.get(), 2);
+
+            let mut iter = values.into_iter();
+            iter.next();
+            assert_eq!(drops.get(), 3);
+        }
+        assert_eq!(drops.get(), 6);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::alloc::*;
+use core::ffi::c_void;
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+
+/// Owning pointer to a heap value, generic over the allocator like `CVec`.
+pub struct CBox<T: ?Sized, A: Allocator> {
+    ptr: NonNull<T>,
+    alloc: A,
+}
+
+impl<T, A: Allocator + Default> CBox<T, A> {
+    pub fn new(value: T) -> Self {
+        Self::new_in(value, A::default())
+    }
+}
+
+impl<T, A: Allocator> CBox<T, A> {
+    pub fn new_in(value: T, mut alloc: A) -> Self {
+        let layout = Layout::from_type::<T>();
+        let ptr = if layout.size == 0 {
+            NonNull::dangling()
+        } else {
+            let ptr = unsafe { alloc.alloc(layout).expect("Allocation error") as *mut T };
+            unsafe { NonNull::new_unchecked(ptr) }
+        };
+
+        unsafe {
+            ptr.as_ptr().write(value);
+        }
+        Self { ptr, alloc }
+    }
+
+    pub fn into_inner(boxed: Self) -> T {
+        let (ptr, mut alloc) = Self::into_raw_with_allocator(boxed);
+        unsafe {
+            let value = ptr.read();
+            let layout = Layout::from_type::<T>();
+            if layout.size != 0 {
+                alloc.dealloc(ptr as *mut c_void, layout);
+            }
+            value
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> CBox<T, A> {
+    /// # Safety
+    /// `ptr` must point to an initialized value allocated by `alloc` with
+    /// the layout of that value (or be dangling for zero sized values).
+    pub unsafe fn from_raw_in(ptr: *mut T, alloc: A) -> Self {
+        Self {
+            ptr: NonNull::new_unchecked(ptr),
+            alloc,
+        }
+    }
+
+    pub fn into_raw_with_allocator(boxed: Self) -> (*mut T, A) {
+        let boxed = mem::ManuallyDrop::new(boxed);
+        (boxed.ptr.as_ptr(), unsafe { ptr::read(&boxed.alloc) })
+    }
+
+    pub fn as_ptr(boxed: &Self) -> *const T {
+        boxed.ptr.as_ptr()
+    }
+
+    pub fn as_mut_ptr(boxed: &mut Self) -> *mut T {
+        boxed.ptr.as_ptr()
+    }
+
+    /// Turns the box into a box of an unsized type (`dyn Trait`, slice...),
+    /// prefer the `cbox_dyn!` macro which does the cast for you.
+    ///
+    /// # Safety
+    /// `f` must return the pointer it is given, only adding metadata.
+    pub unsafe fn cast_unsized<U: ?Sized, F: FnOnce(*mut T) -> *mut U>(boxed: Self, f: F) -> CBox<U, A> {
+        let (ptr, alloc) = Self::into_raw_with_allocator(boxed);
+        CBox::from_raw_in(f(ptr), alloc)
+    }
+}
+
+/// Converts a `CBox<T, A>` into a `CBox<dyn Trait, A>` (or any type `T` unsizes to),
+/// standing in for the unstable `CoerceUnsized`:
+///
+/// ```ignore
+/// let scene: CBox<dyn Scene, SystemAllocator> = cbox_dyn!(CBox::new(Intro::new()), dyn Scene);
+/// ```
+#[macro_export]
+macro_rules! cbox_dyn {
+    ($boxed:expr, $ty:ty) => {{
+        let mut boxed = $boxed;
+        let sized_ptr = $crate::boxed::CBox::as_mut_ptr(&mut boxed);
+        // The implicit coercion only compiles for genuine unsizing casts
+        let unsized_ptr: *mut $ty = sized_ptr;
+        unsafe { $crate::boxed::CBox::cast_unsized(boxed, |_| unsized_ptr) }
+    }};
+}
+
+impl<T: ?Sized, A: Allocator> Drop for CBox<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            let layout = Layout::for_value(self.ptr.as_ref());
+            ptr::drop_in_place(self.ptr.as_ptr());
+            if layout.size != 0 {
+                self.alloc.dealloc(self.ptr.as_ptr() as *mut c_void, layout);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Deref for CBox<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> DerefMut for CBox<T, A> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for CBox<T, A> {
+    fn clone(&self) -> Self {
+        Self::new_in((**self).clone(), self.alloc.clone())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, A: Allocator> fmt::Debug for CBox<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::SystemAllocator;
+    use core::cell::Cell;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    trait Scene {
+        fn frame(&mut self) -> u32;
+    }
+
+    struct Intro<'a> {
+        frames: u32,
+        _counter: DropCounter<'a>,
+    }
+
+    impl<'a> Scene for Intro<'a> {
+        fn frame(&mut self) -> u32 {
+            self.frames += 1;
+            self.frames
+        }
+    }
+
+    #[test]
+    fn test_box() {
+        let mut boxed: CBox<[u64; 4], SystemAllocator> = CBox::new([1, 2, 3, 4]);
+        boxed[2] = 7;
+        assert_eq!(*boxed, [1, 2, 7, 4]);
+        let copy = boxed.clone();
+        assert_eq!(CBox::into_inner(copy), [1, 2, 7, 4]);
+
+        let unit: CBox<(), SystemAllocator> = CBox::new(());
+        assert_eq!(*unit, ());
+    }
+
+    #[test]
+    fn test_box_drop() {
+        let drops = Cell::new(0);
+        {
+            let _boxed: CBox<DropCounter, SystemAllocator> = CBox::new(DropCounter(&drops));
+        }
+        assert_eq!(drops.get(), 1);
+        let boxed: CBox<DropCounter, SystemAllocator> = CBox::new(DropCounter(&drops));
+        let inner = CBox::into_inner(boxed);
+        assert_eq!(drops.get(), 1);
+        drop(inner);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn test_box_dyn() {
+        let drops = Cell::new(0);
+        {
+            let intro: CBox<Intro, SystemAllocator> = CBox::new(Intro { frames: 0, _counter: DropCounter(&drops) });
+            let mut scene: CBox<dyn Scene, SystemAllocator> = cbox_dyn!(intro, dyn Scene);
+            scene.frame();
+            assert_eq!(scene.frame(), 2);
+        }
+        assert_eq!(drops.get(), 1);
+
+        let slice: CBox<[u32], SystemAllocator> = cbox_dyn!(CBox::new([1u32, 2, 3]), [u32]);
+        assert_eq!(slice.len(), 3);
+        assert_eq!(slice[2], 3);
+    }
+}
@@ -0,0 +1,520 @@
+use crate::alloc::Allocator;
+use crate::vec::CVec;
+use core::fmt;
+use core::str;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReadError {
+    /// Reading `needed` bytes at `position` went past the end of the data.
+    UnexpectedEnd { position: usize, needed: usize },
+    /// A varint doesn't fit in 64 bits.
+    VarintOverflow { position: usize },
+    /// A length-prefixed string isn't valid UTF-8.
+    InvalidUtf8 { position: usize },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ReadError::UnexpectedEnd { position, needed } => {
+                write!(f, "unexpected end of data reading {} bytes at offset {}", needed, position)
+            }
+            ReadError::VarintOverflow { position } => write!(f, "varint overflow at offset {}", position),
+            ReadError::InvalidUtf8 { position } => write!(f, "invalid UTF-8 string at offset {}", position),
+        }
+    }
+}
+
+/// Converts to the nearest half-float, ties to even. Out of range values become infinities.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        // Keep NaNs quiet and non-zero
+        let nan = if mant != 0 { 0x200 | (mant >> 13) as u16 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exp = exp - 127 + 15;
+    if half_exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    let (half, rest, halfway) = if half_exp <= 0 {
+        if half_exp < -10 {
+            return sign;
+        }
+        // Subnormal, the implicit bit becomes explicit
+        let full = mant | 0x80_0000;
+        let shift = (14 - half_exp) as u32;
+        (full >> shift, full & ((1 << shift) - 1), 1 << (shift - 1))
+    } else {
+        (((half_exp as u32) << 10) | (mant >> 13), mant & 0x1fff, 0x1000)
+    };
+
+    // A carry out of the mantissa correctly bumps the exponent
+    let rounded = if rest > halfway || (rest == halfway && half & 1 == 1) { half + 1 } else { half };
+    sign | rounded as u16
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exp = ((half >> 10) & 0x1f) as u32;
+    let mant = (half & 0x3ff) as u32;
+
+    match exp {
+        0 => {
+            // Subnormals are exact in f32
+            let value = mant as f32 / (1 << 24) as f32;
+            f32::from_bits(sign | value.to_bits())
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mant << 13)),
+        _ => f32::from_bits(sign | ((exp + 127 - 15) << 23) | (mant << 13)),
+    }
+}
+
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Serializes into a `CVec<u8, A>`: little-endian numbers, LEB128 varints,
+/// half-floats, bit fields and length-prefixed bytes.
+///
+/// Bits are packed from the least significant one. Any byte-level write
+/// first pads the pending bits to a whole byte with zeros.
+pub struct ByteWriter<A: Allocator> {
+    bytes: CVec<u8, A>,
+    bits: u64,
+    bit_count: u32,
+}
+
+impl<A: Allocator + Default> ByteWriter<A> {
+    pub fn new() -> Self {
+        Self::new_in(A::default())
+    }
+}
+
+impl<A: Allocator + Default> Default for ByteWriter<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> ByteWriter<A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self::from_vec(CVec::new_in(alloc))
+    }
+
+    /// Appends to the end of `bytes`.
+    pub fn from_vec(bytes: CVec<u8, A>) -> Self {
+        Self { bytes, bits: 0, bit_count: 0 }
+    }
+
+    /// Bytes written so far, pending bits included.
+    pub fn len(&self) -> usize {
+        self.bytes.len() + (self.bit_count as usize).div_ceil(8)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn into_inner(mut self) -> CVec<u8, A> {
+        self.align();
+        self.bytes
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.align();
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.write_bytes(&[value]);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_i8(&mut self, value: i8) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.write_u16(value as u16);
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.write_u32(value as u32);
+    }
+
+    pub fn write_i64(&mut self, value: i64) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    /// Stores `value` on 2 bytes, see `f32_to_f16` for the rounding.
+    pub fn write_f16(&mut self, value: f32) {
+        self.write_u16(f32_to_f16(value));
+    }
+
+    /// LEB128: 7 bits per byte, so values below 128 take a single byte.
+    pub fn write_var_u64(&mut self, mut value: u64) {
+        self.align();
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    /// Zigzag then LEB128, so small negative values stay small too.
+    pub fn write_var_i64(&mut self, value: i64) {
+        self.write_var_u64(zigzag_encode(value));
+    }
+
+    /// Writes the low `count` bits of `value`, `count` being at most 32.
+    pub fn write_bits(&mut self, value: u32, count: u32) {
+        assert!(count <= 32, "Can't write {} bits at once", count);
+        let value = if count == 32 { value } else { value & ((1 << count) - 1) };
+        self.bits |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits(value as u32, 1);
+    }
+
+    /// Pads the pending bits to a whole byte.
+    pub fn align(&mut self) {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bits as u8);
+            self.bits = 0;
+            self.bit_count = 0;
+        }
+    }
+
+    /// Varint length followed by the bytes.
+    pub fn write_len_bytes(&mut self, bytes: &[u8]) {
+        self.write_var_u64(bytes.len() as u64);
+        self.write_bytes(bytes);
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.write_len_bytes(s.as_bytes());
+    }
+}
+
+/// Reads what `ByteWriter` wrote, borrowing the data. Every read checks
+/// the remaining length and reports a `ReadError` instead of panicking.
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bits: u64,
+    bit_count: u32,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0, bits: 0, bit_count: 0 }
+    }
+
+    /// Offset of the next byte to read.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ReadError> {
+        self.align();
+        if len > self.remaining() {
+            return Err(ReadError::UnexpectedEnd { position: self.position, needed: len });
+        }
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ReadError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ReadError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, ReadError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, ReadError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, ReadError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8, ReadError> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, ReadError> {
+        Ok(self.read_u16()? as i16)
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, ReadError> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64, ReadError> {
+        Ok(self.read_u64()? as i64)
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, ReadError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, ReadError> {
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+
+    pub fn read_f16(&mut self) -> Result<f32, ReadError> {
+        Ok(f16_to_f32(self.read_u16()?))
+    }
+
+    pub fn read_var_u64(&mut self) -> Result<u64, ReadError> {
+        self.align();
+        let start = self.position;
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let needed = self.position - start + 1;
+            let byte = self.read_u8().map_err(|_| ReadError::UnexpectedEnd { position: start, needed })?;
+            // The 10th byte only has room for the 64th bit
+            if shift == 63 && byte > 1 {
+                return Err(ReadError::VarintOverflow { position: start });
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn read_var_i64(&mut self) -> Result<i64, ReadError> {
+        Ok(zigzag_decode(self.read_var_u64()?))
+    }
+
+    /// Reads `count` bits, at most 32, in the order `write_bits` wrote them.
+    pub fn read_bits(&mut self, count: u32) -> Result<u32, ReadError> {
+        assert!(count <= 32, "Can't read {} bits at once", count);
+        while self.bit_count < count {
+            let byte = match self.data.get(self.position) {
+                Some(&byte) => byte,
+                None => return Err(ReadError::UnexpectedEnd { position: self.position, needed: 1 }),
+            };
+            self.position += 1;
+            self.bits |= (byte as u64) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let value = (self.bits & ((1u64 << count) - 1)) as u32;
+        self.bits >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, ReadError> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    /// Skips the padding bits of the current byte.
+    pub fn align(&mut self) {
+        self.bits = 0;
+        self.bit_count = 0;
+    }
+
+    pub fn read_len_bytes(&mut self) -> Result<&'a [u8], ReadError> {
+        let start = self.position;
+        let len = self.read_var_u64()?;
+        if len > self.remaining() as u64 {
+            return Err(ReadError::UnexpectedEnd { position: start, needed: (self.position - start).saturating_add(len as usize) });
+        }
+        self.read_bytes(len as usize)
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str, ReadError> {
+        let start = self.position;
+        let bytes = self.read_len_bytes()?;
+        str::from_utf8(bytes).map_err(|_| ReadError::InvalidUtf8 { position: start })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::SystemAllocator;
+
+    type Writer = ByteWriter<SystemAllocator>;
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut writer = Writer::new();
+        writer.write_u8(0xab);
+        writer.write_u16(0x1234);
+        writer.write_i32(-2);
+        writer.write_u64(u64::MAX - 1);
+        writer.write_f32(0.1);
+        writer.write_f64(-1e300);
+        writer.write_str("Démo");
+        writer.write_len_bytes(&[]);
+        let bytes = writer.into_inner();
+        assert_eq!(&bytes[..7], &[0xab, 0x34, 0x12, 0xfe, 0xff, 0xff, 0xff]);
+
+        let mut reader = ByteReader::new(&bytes);
+        assert_eq!(reader.read_u8(), Ok(0xab));
+        assert_eq!(reader.read_u16(), Ok(0x1234));
+        assert_eq!(reader.read_i32(), Ok(-2));
+        assert_eq!(reader.read_u64(), Ok(u64::MAX - 1));
+        assert_eq!(reader.read_f32(), Ok(0.1));
+        assert_eq!(reader.read_f64(), Ok(-1e300));
+        assert_eq!(reader.read_str(), Ok("Démo"));
+        assert_eq!(reader.read_len_bytes(), Ok(&[][..]));
+        assert!(reader.is_empty());
+        assert_eq!(reader.read_u16(), Err(ReadError::UnexpectedEnd { position: bytes.len(), needed: 2 }));
+    }
+
+    #[test]
+    fn test_varints() {
+        let values = [0, 1, 127, 128, 300, 16_383, 16_384, u32::MAX as u64, u64::MAX];
+        let signed = [0, -1, 1, -64, 64, i32::MIN as i64, i64::MAX, i64::MIN];
+        let mut writer = Writer::new();
+        for &v in &values {
+            writer.write_var_u64(v);
+        }
+        for &v in &signed {
+            writer.write_var_i64(v);
+        }
+        let bytes = writer.into_inner();
+
+        let mut reader = ByteReader::new(&bytes);
+        for &v in &values {
+            assert_eq!(reader.read_var_u64(), Ok(v));
+        }
+        for &v in &signed {
+            assert_eq!(reader.read_var_i64(), Ok(v));
+        }
+
+        let mut writer = Writer::new();
+        writer.write_var_u64(300);
+        writer.write_var_i64(-64);
+        writer.write_var_i64(64);
+        assert_eq!(writer.into_inner().as_slice(), &[0xac, 0x02, 0x7f, 0x80, 0x01]);
+
+        let overflow = [0xff; 10];
+        assert_eq!(ByteReader::new(&overflow).read_var_u64(), Err(ReadError::VarintOverflow { position: 0 }));
+        assert_eq!(ByteReader::new(&[0x80, 0x80]).read_var_u64(), Err(ReadError::UnexpectedEnd { position: 0, needed: 3 }));
+    }
+
+    #[test]
+    fn test_f16() {
+        let exact = [0.0, -0.0, 1.0, -2.5, 0.5, 65504.0, 6.1035156e-5, 5.9604645e-8, f32::INFINITY, f32::NEG_INFINITY];
+        for &v in &exact {
+            let half = f32_to_f16(v);
+            assert_eq!(f16_to_f32(half).to_bits(), v.to_bits(), "{}", v);
+        }
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1e-9), 0);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        // 1 + 2^-11 is halfway between 1 and the next half, ties to even
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 / 2048.0), 0x3c02);
+        assert!((f16_to_f32(f32_to_f16(0.1)) - 0.1).abs() < 1e-4);
+
+        let mut writer = Writer::new();
+        writer.write_f16(-2.5);
+        let bytes = writer.into_inner();
+        assert_eq!(ByteReader::new(&bytes).read_f16(), Ok(-2.5));
+    }
+
+    #[test]
+    fn test_bits() {
+        // A quantized vertex: 11 + 11 + 10 bits, then a flag
+        let mut writer = Writer::new();
+        writer.write_bits(1500, 11);
+        writer.write_bits(3, 11);
+        writer.write_bits(1023, 10);
+        writer.write_bool(true);
+        assert_eq!(writer.len(), 5);
+        writer.write_u8(0x42);
+        writer.write_bits(u32::MAX, 32);
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), 10);
+
+        let mut reader = ByteReader::new(&bytes);
+        assert_eq!(reader.read_bits(11), Ok(1500));
+        assert_eq!(reader.read_bits(11), Ok(3));
+        assert_eq!(reader.read_bits(10), Ok(1023));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u8(), Ok(0x42));
+        assert_eq!(reader.read_bits(32), Ok(u32::MAX));
+        assert_eq!(reader.read_bits(1), Err(ReadError::UnexpectedEnd { position: 10, needed: 1 }));
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let mut writer = Writer::new();
+        writer.write_var_u64(10);
+        writer.write_bytes(b"abc");
+        let bytes = writer.into_inner();
+        let mut reader = ByteReader::new(&bytes);
+        assert_eq!(reader.read_len_bytes(), Err(ReadError::UnexpectedEnd { position: 0, needed: 11 }));
+
+        let invalid = [2, 0xc3, 0x28];
+        assert_eq!(ByteReader::new(&invalid).read_str(), Err(ReadError::InvalidUtf8 { position: 0 }));
+        assert_eq!(
+            crate::cformat!("{}", ReadError::UnexpectedEnd { position: 4, needed: 2 }),
+            "unexpected end of data reading 2 bytes at offset 4"
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use crate::alloc::*;
+use crate::hashmap::CHashMap;
+use crate::vec::CVec;
+use core::cell::UnsafeCell;
+use core::cmp::max;
+use core::ffi::c_void;
+use core::fmt;
+use core::ptr;
+
+const GUARD_SIZE: usize = 16;
+const CANARY: u8 = 0xFD;
+// Fresh blocks, to make reads of uninitialized memory stand out
+const CLEAN: u8 = 0xCD;
+const POISON: u8 = 0xDD;
+// Freed blocks kept back so double frees and writes after free can be caught
+const QUARANTINE_LEN: usize = 64;
+
+/// Memory error found by `CheckedAllocator`, addresses are the ones seen by the caller.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CheckError {
+    DoubleFree { ptr: usize, size: usize },
+    /// Freed pointer that was never allocated, `size` is the one it was freed with.
+    UnknownFree { ptr: usize, size: usize },
+    /// Freed with a layout different from the one it was allocated with.
+    WrongLayout { ptr: usize, size: usize, layout_size: usize },
+    /// The guard before the block was overwritten.
+    Underrun { ptr: usize, size: usize },
+    /// The guard after the block was overwritten.
+    Overrun { ptr: usize, size: usize },
+    /// A freed block was written to.
+    UseAfterFree { ptr: usize, size: usize },
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CheckError::DoubleFree { ptr, size } => write!(f, "double free of {} bytes at {:#x}", size, ptr),
+            CheckError::UnknownFree { ptr, size } => write!(f, "free of {} bytes at unknown pointer {:#x}", size, ptr),
+            CheckError::WrongLayout { ptr, size, layout_size } => {
+                write!(f, "block of {} bytes at {:#x} freed as {} bytes", size, ptr, layout_size)
+            }
+            CheckError::Underrun { ptr, size } => write!(f, "buffer underrun before {} bytes at {:#x}", size, ptr),
+            CheckError::Overrun { ptr, size } => write!(f, "buffer overrun after {} bytes at {:#x}", size, ptr),
+            CheckError::UseAfterFree { ptr, size } => write!(f, "use after free of {} bytes at {:#x}", size, ptr),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Block {
+    layout: Layout,
+    freed: bool,
+}
+
+struct State<A: Allocator + Clone> {
+    alloc: A,
+    blocks: CHashMap<usize, Block, A>,
+    quarantine: CVec<usize, A>,
+    errors: CVec<CheckError, A>,
+    panic_on_error: bool,
+}
+
+/// Debug allocator: surrounds every block with canaries, fills freed
+/// memory with a poison pattern and checks every free against a table
+/// of the blocks it handed out. Errors panic by default, call
+/// `set_panic_on_error(false)` to only record them.
+pub struct CheckedAllocator<A: Allocator + Clone> {
+    state: UnsafeCell<State<A>>,
+}
+
+impl<A: Allocator + Clone + Default> Default for CheckedAllocator<A> {
+    fn default() -> Self {
+        Self::new(A::default())
+    }
+}
+
+impl<A: Allocator + Clone> CheckedAllocator<A> {
+    pub fn new(alloc: A) -> Self {
+        Self {
+            state: UnsafeCell::new(State {
+                blocks: CHashMap::new_in(alloc.clone()),
+                quarantine: CVec::new_in(alloc.clone()),
+                errors: CVec::new_in(alloc.clone()),
+                alloc,
+                panic_on_error: true,
+            }),
+        }
+    }
+
+    /// Allocator to give to containers while keeping access to the errors.
+    pub fn handle(&self) -> CheckedHandle<'_, A> {
+        CheckedHandle { checked: self }
+    }
+
+    pub fn set_panic_on_error(&mut self, panic_on_error: bool) {
+        self.state.get_mut().panic_on_error = panic_on_error;
+    }
+
+    pub fn errors(&mut self) -> &[CheckError] {
+        &self.state.get_mut().errors
+    }
+
+    pub fn clear_errors(&mut self) {
+        self.state.get_mut().errors.clear();
+    }
+
+    /// Checks the canaries of every live block and the poison of the
+    /// quarantined ones, without waiting for them to be freed.
+    pub fn check(&self) {
+        let state = unsafe { &mut *self.state.get() };
+        let mut found: CVec<CheckError, A> = CVec::new_in(state.alloc.clone());
+        for (&ptr, block) in state.blocks.iter() {
+            if block.freed {
+                found.extend(check_poison(ptr, block.layout));
+            } else {
+                found.extend(check_guards(ptr, block.layout));
+            }
+        }
+        for &error in found.iter() {
+            state.report(error);
+        }
+    }
+
+    unsafe fn checked_alloc(&self, layout: Layout) -> Option<*mut c_void> {
+        let state = &mut *self.state.get();
+        let base = state.alloc.alloc(outer_layout(layout))? as *mut u8;
+        let front = front_size(layout.align);
+        ptr::write_bytes(base, CANARY, front);
+        ptr::write_bytes(base.add(front), CLEAN, layout.size);
+        ptr::write_bytes(base.add(front + layout.size), CANARY, GUARD_SIZE);
+
+        let ptr = base.add(front) as usize;
+        state.blocks.insert(ptr, Block { layout, freed: false });
+        Some(ptr as *mut c_void)
+    }
+
+    unsafe fn checked_dealloc(&self, ptr: *mut c_void, layout: Layout) {
+        let state = &mut *self.state.get();
+        let key = ptr as usize;
+        let block = match state.blocks.get(&key).copied() {
+            None => return state.report(CheckError::UnknownFree { ptr: key, size: layout.size }),
+            Some(block) if block.freed => {
+                return state.report(CheckError::DoubleFree { ptr: key, size: block.layout.size })
+            }
+            Some(block) => block,
+        };
+
+        if block.layout.size != layout.size || block.layout.align != layout.align {
+            state.report(CheckError::WrongLayout { ptr: key, size: block.layout.size, layout_size: layout.size });
+        }
+        for error in check_guards(key, block.layout) {
+            state.report(error);
+        }
+
+        ptr::write_bytes(ptr as *mut u8, POISON, block.layout.size);
+        state.blocks.insert(key, Block { freed: true, ..block });
+        state.quarantine.push(key);
+        if state.quarantine.len() > QUARANTINE_LEN {
+            let oldest = state.quarantine.remove(0);
+            state.release(oldest);
+        }
+    }
+}
+
+impl<A: Allocator + Clone> State<A> {
+    fn report(&mut self, error: CheckError) {
+        self.errors.push(error);
+        if self.panic_on_error {
+            panic!("CheckedAllocator: {}", error);
+        }
+    }
+
+    // Gives a quarantined block back to the wrapped allocator
+    unsafe fn release(&mut self, ptr: usize) {
+        let block = self.blocks.remove(&ptr).expect("Quarantined block missing from the table");
+        for error in check_poison(ptr, block.layout) {
+            self.report(error);
+        }
+        let base = (ptr as *mut u8).sub(front_size(block.layout.align));
+        self.alloc.dealloc(base as *mut c_void, outer_layout(block.layout));
+    }
+}
+
+impl<A: Allocator + Clone> Drop for CheckedAllocator<A> {
+    fn drop(&mut self) {
+        let state = self.state.get_mut();
+        // Don't panic again while unwinding
+        state.panic_on_error = false;
+        while let Some(ptr) = state.quarantine.pop() {
+            unsafe { state.release(ptr) };
+        }
+    }
+}
+
+// Canaries in front of the block, keeping it aligned
+fn front_size(align: usize) -> usize {
+    max(GUARD_SIZE, align)
+}
+
+fn outer_layout(layout: Layout) -> Layout {
+    Layout::new(front_size(layout.align) + layout.size + GUARD_SIZE, layout.align)
+}
+
+fn is_filled(start: *const u8, len: usize, value: u8) -> bool {
+    (0..len).all(|i| unsafe { start.add(i).read() } == value)
+}
+
+fn check_guards(ptr: usize, layout: Layout) -> impl Iterator<Item = CheckError> {
+    let start = ptr as *const u8;
+    let front = front_size(layout.align);
+    let underrun = !is_filled(unsafe { start.sub(front) }, front, CANARY);
+    let overrun = !is_filled(unsafe { start.add(layout.size) }, GUARD_SIZE, CANARY);
+    let size = layout.size;
+    let underrun = if underrun { Some(CheckError::Underrun { ptr, size }) } else { None };
+    let overrun = if overrun { Some(CheckError::Overrun { ptr, size }) } else { None };
+    underrun.into_iter().chain(overrun)
+}
+
+fn check_poison(ptr: usize, layout: Layout) -> impl Iterator<Item = CheckError> {
+    let size = layout.size;
+    let used = !is_filled(ptr as *const u8, size, POISON);
+    let error = if used { Some(CheckError::UseAfterFree { ptr, size }) } else { None };
+    error.into_iter().chain(check_guards(ptr, layout))
+}
+
+impl<A: Allocator + Clone> Allocator for CheckedAllocator<A> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        self.checked_alloc(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout) {
+        self.checked_dealloc(ptr, layout)
+    }
+}
+
+pub struct CheckedHandle<'a, A: Allocator + Clone> {
+    checked: &'a CheckedAllocator<A>,
+}
+
+impl<'a, A: Allocator + Clone> Clone for CheckedHandle<'a, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, A: Allocator + Clone> Copy for CheckedHandle<'a, A> {}
+
+impl<'a, A: Allocator + Clone> Allocator for CheckedHandle<'a, A> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        self.checked.checked_alloc(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout) {
+        self.checked.checked_dealloc(ptr, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::string::CString;
+    use crate::system::SystemAllocator;
+
+    type Checked = CheckedAllocator<SystemAllocator>;
+
+    fn recording() -> Checked {
+        let mut checked = Checked::default();
+        checked.set_panic_on_error(false);
+        checked
+    }
+
+    #[test]
+    fn test_checked_clean() {
+        let mut checked = Checked::default();
+        {
+            let mut cvec: CVec<u32, _> = CVec::new_in(checked.handle());
+            for i in 0..1000 {
+                cvec.push(i);
+            }
+            cvec.retain(|&i| i % 3 == 0);
+            cvec.shrink_to_fit();
+            let mut name = CString::from_str_in("glBufferData", checked.handle());
+            fmt::Write::write_str(&mut name, "ARB").unwrap();
+            checked.check();
+        }
+        checked.check();
+        assert!(checked.errors().is_empty());
+    }
+
+    #[test]
+    fn test_checked_frees() {
+        let mut checked = recording();
+        unsafe {
+            let layout = Layout::new(24, 8);
+            let ptr = checked.alloc(layout).unwrap();
+            assert_eq!(ptr as usize % 8, 0);
+            assert_eq!((ptr as *const u8).read(), CLEAN);
+            checked.dealloc(ptr, layout);
+            assert_eq!((ptr as *const u8).add(23).read(), POISON);
+            checked.dealloc(ptr, layout);
+            checked.dealloc(0x1234 as *mut c_void, layout);
+
+            let other = checked.alloc(Layout::new(64, 64)).unwrap();
+            assert_eq!(other as usize % 64, 0);
+            checked.dealloc(other, Layout::new(32, 64));
+
+            assert_eq!(checked.errors(), &[
+                CheckError::DoubleFree { ptr: ptr as usize, size: 24 },
+                CheckError::UnknownFree { ptr: 0x1234, size: 24 },
+                CheckError::WrongLayout { ptr: other as usize, size: 64, layout_size: 32 },
+            ]);
+            let text: crate::ArrayString<64> = crate::aformat!("{}", checked.errors()[1]);
+            assert_eq!(text, "free of 24 bytes at unknown pointer 0x1234");
+        }
+    }
+
+    #[test]
+    fn test_checked_corruption() {
+        let mut checked = recording();
+        unsafe {
+            let layout = Layout::new(10, 1);
+            let ptr = checked.alloc(layout).unwrap() as *mut u8;
+            ptr.add(10).write(0);
+            ptr.sub(1).write(0);
+            checked.check();
+            assert_eq!(checked.errors(), &[
+                CheckError::Underrun { ptr: ptr as usize, size: 10 },
+                CheckError::Overrun { ptr: ptr as usize, size: 10 },
+            ]);
+            checked.clear_errors();
+
+            let freed = checked.alloc(layout).unwrap() as *mut u8;
+            checked.dealloc(freed as *mut c_void, layout);
+            freed.add(3).write(42);
+            // Caught when the block leaves the quarantine
+            for _ in 0..QUARANTINE_LEN {
+                let p = checked.alloc(Layout::new(8, 8)).unwrap();
+                checked.dealloc(p, Layout::new(8, 8));
+            }
+            assert_eq!(checked.errors(), &[CheckError::UseAfterFree { ptr: freed as usize, size: 10 }]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "double free of 16 bytes")]
+    fn test_checked_panics() {
+        let mut checked = Checked::default();
+        unsafe {
+            let ptr = checked.alloc(Layout::new(16, 8)).unwrap();
+            checked.dealloc(ptr, Layout::new(16, 8));
+            checked.dealloc(ptr, Layout::new(16, 8));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::alloc::{Allocator, Layout};
+use crate::once::Once;
+use crate::sync::Backoff;
+use core::alloc::{GlobalAlloc, Layout as CoreLayout};
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+impl From<CoreLayout> for Layout {
+    fn from(layout: CoreLayout) -> Self {
+        Layout::new(layout.size(), layout.align())
+    }
+}
+
+/// Makes any simple-alloc `Allocator` usable as the `#[global_allocator]`,
+/// so `alloc::vec::Vec`, `Box`, `format!`... work in no_std binaries:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: GlobalAllocator<Win32HeapAllocator> = GlobalAllocator::new();
+/// ```
+///
+/// The allocator is created with `Default` on first use, and calls are
+/// serialized with a spin lock since `Allocator` methods take `&mut self`.
+pub struct GlobalAllocator<A: Allocator + Default> {
+    init: Once,
+    lock: AtomicBool,
+    inner: UnsafeCell<MaybeUninit<A>>,
+}
+
+unsafe impl<A: Allocator + Default + Send> Sync for GlobalAllocator<A> {}
+
+impl<A: Allocator + Default> GlobalAllocator<A> {
+    pub const fn new() -> Self {
+        Self {
+            init: Once::INIT,
+            lock: AtomicBool::new(false),
+            inner: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    fn with_allocator<R, F: FnOnce(&mut A) -> R>(&self, f: F) -> R {
+        self.init.run_once(|| unsafe {
+            (*self.inner.get()).as_mut_ptr().write(A::default());
+        });
+
+        let mut backoff = Backoff::new();
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            backoff.snooze();
+        }
+        let res = f(unsafe { &mut *(*self.inner.get()).as_mut_ptr() });
+        self.lock.store(false, Ordering::Release);
+        res
+    }
+}
+
+impl<A: Allocator + Default> Default for GlobalAllocator<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<A: Allocator + Default + Send> GlobalAlloc for GlobalAllocator<A> {
+    unsafe fn alloc(&self, layout: CoreLayout) -> *mut u8 {
+        self.with_allocator(|a| a.alloc(layout.into()))
+            .map_or(ptr::null_mut(), |p| p as *mut u8)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: CoreLayout) {
+        self.with_allocator(|a| a.dealloc(ptr as *mut c_void, layout.into()))
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: CoreLayout, new_size: usize) -> *mut u8 {
+        self.with_allocator(|a| a.realloc(ptr as *mut c_void, layout.into(), new_size))
+            .map_or(ptr::null_mut(), |p| p as *mut u8)
+    }
+}
+
+/// Ready-made body for the binary's `#[alloc_error_handler]`: reports the
+/// failed request through the panic handler.
+pub fn alloc_error(layout: CoreLayout) -> ! {
+    panic!("memory allocation of {} bytes (align {}) failed", layout.size(), layout.align())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::system::SystemAllocator;
+    use std::thread;
+    use std::vec::Vec;
+
+    static ALLOCATOR: GlobalAllocator<SystemAllocator> = GlobalAllocator::new();
+
+    #[test]
+    fn test_global_alloc() {
+        unsafe {
+            let layout = CoreLayout::from_size_align(100, 64).unwrap();
+            let ptr = ALLOCATOR.alloc(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % 64, 0);
+            ptr.write_bytes(0xab, 100);
+            let ptr = ALLOCATOR.realloc(ptr, layout, 1000);
+            assert_eq!(ptr.add(99).read(), 0xab);
+            ALLOCATOR.dealloc(ptr, CoreLayout::from_size_align(1000, 64).unwrap());
+
+            let zeroed = ALLOCATOR.alloc_zeroed(CoreLayout::new::<[u64; 8]>()) as *mut [u64; 8];
+            assert_eq!(*zeroed, [0; 8]);
+            ALLOCATOR.dealloc(zeroed as *mut u8, CoreLayout::new::<[u64; 8]>());
+        }
+    }
+
+    #[test]
+    fn test_global_alloc_threads() {
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                thread::spawn(move || unsafe {
+                    let layout = CoreLayout::new::<u64>();
+                    for i in 0..200 {
+                        let ptr = ALLOCATOR.alloc(layout) as *mut u64;
+                        ptr.write(t * 1000 + i);
+                        assert_eq!(ptr.read(), t * 1000 + i);
+                        ALLOCATOR.dealloc(ptr as *mut u8, layout);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+}
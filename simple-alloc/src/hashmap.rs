@@ -0,0 +1,609 @@
+use crate::alloc::*;
+use core::borrow::Borrow;
+use core::ffi::c_void;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+
+const MIN_CAPACITY: usize = 8;
+
+/// FNV-1a, small and good enough for the short keys we hash (names, ids, pointers).
+pub struct FnvHasher {
+    state: u64,
+}
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self {
+            state: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.state ^= b as u64;
+            self.state = self.state.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+fn hash_key<Q: Hash + ?Sized>(key: &Q) -> u64 {
+    let mut hasher = FnvHasher::default();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+enum Bucket<K, V> {
+    Empty,
+    Deleted,
+    Full(K, V),
+}
+
+/// Open addressing hash map (linear probing) generic over the allocator, like `CVec`.
+pub struct CHashMap<K, V, A: Allocator> {
+    buckets: *mut Bucket<K, V>,
+    capacity: usize,
+    length: usize,
+    // Full and deleted buckets, used to decide when to rehash
+    used: usize,
+    alloc: A,
+}
+
+impl<K, V, A: Allocator + Default> CHashMap<K, V, A> {
+    pub fn new() -> Self {
+        Self::new_in(A::default())
+    }
+}
+
+impl<K, V, A: Allocator + Default> Default for CHashMap<K, V, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, A: Allocator> CHashMap<K, V, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self {
+            buckets: ptr::null_mut(),
+            capacity: 0,
+            length: 0,
+            used: 0,
+            alloc,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        for i in 0..self.capacity {
+            unsafe {
+                let bucket = self.buckets.add(i);
+                if let Bucket::Empty = *bucket {
+                    continue;
+                }
+                ptr::drop_in_place(bucket);
+                bucket.write(Bucket::Empty);
+            }
+        }
+        self.length = 0;
+        self.used = 0;
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets,
+            current: 0,
+            capacity: self.capacity,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            buckets: self.buckets,
+            current: 0,
+            capacity: self.capacity,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.iter_mut().map(|(_, v)| v)
+    }
+
+    fn bucket(&self, index: usize) -> &Bucket<K, V> {
+        unsafe { &*self.buckets.add(index) }
+    }
+
+    fn bucket_mut(&mut self, index: usize) -> &mut Bucket<K, V> {
+        unsafe { &mut *self.buckets.add(index) }
+    }
+}
+
+impl<K: Hash + Eq, V, A: Allocator> CHashMap<K, V, A> {
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => match self.bucket(index) {
+                Bucket::Full(_, v) => Some(v),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => match self.bucket_mut(index) {
+                Bucket::Full(_, v) => Some(v),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.length -= 1;
+        match mem::replace(self.bucket_mut(index), Bucket::Deleted) {
+            Bucket::Full(_, v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, A> {
+        let hash = hash_key(&key);
+        match self.find_hashed(hash, &key) {
+            Some(index) => Entry::Occupied(OccupiedEntry { map: self, index }),
+            None => {
+                // Make room now so the vacant entry can be filled without rehashing
+                self.reserve_one();
+                Entry::Vacant(VacantEntry { map: self, hash, key })
+            }
+        }
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.length == 0 {
+            return None;
+        }
+        self.find_hashed(hash_key(key), key)
+    }
+
+    fn find_hashed<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let mask = self.capacity - 1;
+        let mut index = hash as usize & mask;
+        for _ in 0..self.capacity {
+            match self.bucket(index) {
+                Bucket::Empty => return None,
+                Bucket::Full(k, _) if k.borrow() == key => return Some(index),
+                _ => {}
+            }
+            index = (index + 1) & mask;
+        }
+        None
+    }
+
+    // First empty or deleted bucket on the probe sequence of `hash`
+    fn find_free(&self, hash: u64) -> usize {
+        let mask = self.capacity - 1;
+        let mut index = hash as usize & mask;
+        loop {
+            match self.bucket(index) {
+                Bucket::Full(_, _) => index = (index + 1) & mask,
+                _ => return index,
+            }
+        }
+    }
+
+    fn reserve_one(&mut self) {
+        // Keep the load factor (tombstones included) under 3/4
+        if (self.used + 1) * 4 <= self.capacity * 3 {
+            return;
+        }
+
+        let new_capacity = if self.capacity == 0 {
+            MIN_CAPACITY
+        } else if (self.length + 1) * 2 > self.capacity {
+            self.capacity * 2
+        } else {
+            // Mostly tombstones: rehash at the same size
+            self.capacity
+        };
+        self.resize(new_capacity);
+    }
+
+    fn resize(&mut self, new_capacity: usize) {
+        let layout = Layout::from_array_type::<Bucket<K, V>>(new_capacity);
+        let buckets = unsafe { self.alloc.alloc(layout).expect("Allocation error") as *mut Bucket<K, V> };
+        for i in 0..new_capacity {
+            unsafe {
+                buckets.add(i).write(Bucket::Empty);
+            }
+        }
+
+        let old_buckets = mem::replace(&mut self.buckets, buckets);
+        let old_capacity = mem::replace(&mut self.capacity, new_capacity);
+        self.used = self.length;
+
+        if old_buckets.is_null() {
+            return;
+        }
+
+        for i in 0..old_capacity {
+            if let Bucket::Full(k, v) = unsafe { old_buckets.add(i).read() } {
+                let index = self.find_free(hash_key(&k));
+                unsafe {
+                    self.buckets.add(index).write(Bucket::Full(k, v));
+                }
+            }
+        }
+
+        unsafe {
+            self.alloc.dealloc(old_buckets as *mut c_void, Layout::from_array_type::<Bucket<K, V>>(old_capacity));
+        }
+    }
+}
+
+impl<K, V, A: Allocator> Drop for CHashMap<K, V, A> {
+    fn drop(&mut self) {
+        if self.buckets.is_null() {
+            return;
+        }
+
+        unsafe {
+            if mem::needs_drop::<Bucket<K, V>>() {
+                for i in 0..self.capacity {
+                    ptr::drop_in_place(self.buckets.add(i));
+                }
+            }
+            self.alloc.dealloc(self.buckets as *mut c_void, Layout::from_array_type::<Bucket<K, V>>(self.capacity));
+        }
+    }
+}
+
+pub enum Entry<'a, K, V, A: Allocator> {
+    Occupied(OccupiedEntry<'a, K, V, A>),
+    Vacant(VacantEntry<'a, K, V, A>),
+}
+
+pub struct OccupiedEntry<'a, K, V, A: Allocator> {
+    map: &'a mut CHashMap<K, V, A>,
+    index: usize,
+}
+
+pub struct VacantEntry<'a, K, V, A: Allocator> {
+    map: &'a mut CHashMap<K, V, A>,
+    hash: u64,
+    key: K,
+}
+
+impl<'a, K: Hash + Eq, V, A: Allocator> Entry<'a, K, V, A> {
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(ref mut entry) = self {
+            f(entry.get_mut());
+        }
+        self
+    }
+
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => &entry.key,
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V: Default, A: Allocator> Entry<'a, K, V, A> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, V, A: Allocator> OccupiedEntry<'a, K, V, A> {
+    pub fn key(&self) -> &K {
+        match self.map.bucket(self.index) {
+            Bucket::Full(k, _) => k,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn get(&self) -> &V {
+        match self.map.bucket(self.index) {
+            Bucket::Full(_, v) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        match self.map.bucket_mut(self.index) {
+            Bucket::Full(_, v) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        match self.map.bucket_mut(self.index) {
+            Bucket::Full(_, v) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.map.length -= 1;
+        match mem::replace(self.map.bucket_mut(self.index), Bucket::Deleted) {
+            Bucket::Full(_, v) => v,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, A: Allocator> VacantEntry<'a, K, V, A> {
+    pub fn insert(self, value: V) -> &'a mut V {
+        let map = self.map;
+        let index = map.find_free(self.hash);
+        if let Bucket::Empty = map.bucket(index) {
+            map.used += 1;
+        }
+        map.length += 1;
+
+        let bucket = map.bucket_mut(index);
+        *bucket = Bucket::Full(self.key, value);
+        match bucket {
+            Bucket::Full(_, v) => v,
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    buckets: *const Bucket<K, V>,
+    current: usize,
+    capacity: usize,
+    _phantom: PhantomData<&'a (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.current < self.capacity {
+            let bucket = unsafe { &*self.buckets.add(self.current) };
+            self.current += 1;
+            if let Bucket::Full(k, v) = bucket {
+                return Some((k, v));
+            }
+        }
+        None
+    }
+}
+
+pub struct IterMut<'a, K, V> {
+    buckets: *mut Bucket<K, V>,
+    current: usize,
+    capacity: usize,
+    _phantom: PhantomData<&'a mut (K, V)>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.current < self.capacity {
+            let bucket = unsafe { &mut *self.buckets.add(self.current) };
+            self.current += 1;
+            if let Bucket::Full(k, v) = bucket {
+                return Some((k, v));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, K, V, A: Allocator> IntoIterator for &'a CHashMap<K, V, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, A: Allocator> IntoIterator for &'a mut CHashMap<K, V, A> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::SystemAllocator;
+    use core::cell::Cell;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_hashmap() {
+        let mut map: CHashMap<u32, u32, SystemAllocator> = CHashMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert(1, 10), None);
+        assert_eq!(map.insert(2, 20), None);
+        assert_eq!(map.insert(1, 11), Some(10));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&1), Some(&11));
+        assert_eq!(map.get(&3), None);
+        *map.get_mut(&2).unwrap() += 1;
+        assert_eq!(map.get(&2), Some(&21));
+        assert_eq!(map.remove(&1), Some(11));
+        assert_eq!(map.remove(&1), None);
+        assert!(!map.contains_key(&1));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_hashmap_grow() {
+        let mut map: CHashMap<usize, usize, SystemAllocator> = CHashMap::new();
+        for i in 0..1000 {
+            map.insert(i, i * 2);
+        }
+        for i in (0..1000).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i * 2));
+        }
+        for i in 0..1000 {
+            assert_eq!(map.get(&i).copied(), if i % 2 == 0 { None } else { Some(i * 2) });
+        }
+        assert_eq!(map.len(), 500);
+        assert_eq!(map.iter().count(), 500);
+        assert_eq!(map.values().sum::<usize>(), (1..1000).step_by(2).map(|i| i * 2).sum());
+    }
+
+    #[test]
+    fn test_hashmap_entry() {
+        let mut map: CHashMap<&str, u32, SystemAllocator> = CHashMap::new();
+        *map.entry("uTime").or_insert(0) += 1;
+        *map.entry("uTime").or_insert(0) += 1;
+        map.entry("uColor").and_modify(|v| *v = 100).or_insert(7);
+        map.entry("uColor").and_modify(|v| *v += 1).or_default();
+        assert_eq!(map.get("uTime"), Some(&2));
+        assert_eq!(map.get("uColor"), Some(&8));
+        if let Entry::Occupied(entry) = map.entry("uTime") {
+            assert_eq!(entry.remove(), 2);
+        }
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_hashmap_entry_no_grow() {
+        let mut map: CHashMap<usize, usize, SystemAllocator> = CHashMap::new();
+        map.insert(0, 0);
+        // Fill up to the point where one more key makes the table grow
+        while (map.len() + 1) * 4 <= map.capacity() * 3 {
+            map.insert(map.len(), 0);
+        }
+        let capacity = map.capacity();
+        *map.entry(0).or_insert(0) += 1;
+        assert_eq!(map.capacity(), capacity);
+        map.entry(map.len()).or_insert(0);
+        assert!(map.capacity() > capacity);
+        assert_eq!(map.get(&0), Some(&1));
+    }
+
+    #[test]
+    fn test_hashmap_drop() {
+        let drops = Cell::new(0);
+        {
+            let mut map: CHashMap<u32, DropCounter, SystemAllocator> = CHashMap::new();
+            for i in 0..20 {
+                map.insert(i, DropCounter(&drops));
+            }
+            map.insert(0, DropCounter(&drops));
+            assert_eq!(drops.get(), 1);
+            map.remove(&1);
+            assert_eq!(drops.get(), 2);
+            for (_, v) in map.iter_mut() {
+                v.0.get();
+            }
+        }
+        assert_eq!(drops.get(), 21);
+
+        let mut map: CHashMap<u32, DropCounter, SystemAllocator> = CHashMap::new();
+        map.insert(0, DropCounter(&drops));
+        map.clear();
+        assert_eq!(drops.get(), 22);
+        assert!(map.is_empty());
+    }
+}
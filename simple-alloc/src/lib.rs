@@ -1,13 +1,49 @@
 #![no_std]
 pub mod alloc;
+pub mod system;
 pub mod vec;
+pub mod pages;
+pub mod virtual_vec;
 pub mod string;
+pub mod arrayvec;
+pub mod arraystring;
 pub mod hashmap;
+pub mod slotmap;
 pub mod once;
+pub mod sync;
+pub mod ring;
+pub mod arena;
+pub mod pool;
+pub mod global;
+pub mod boxed;
+pub mod rc;
+pub mod tracking;
+pub mod checked;
+pub mod bytes;
+pub mod num;
 
 pub use alloc::*;
+pub use system::SystemAllocator;
 pub use vec::CVec;
-pub use string::CString;
-pub use once::Once;
+pub use virtual_vec::VirtualVec;
+pub use string::{CString, CStr};
+pub use arrayvec::{ArrayVec, CapacityError};
+pub use arraystring::ArrayString;
+pub use hashmap::CHashMap;
+pub use slotmap::{SlotMap, Handle};
+pub use once::{Once, OnceCell, Lazy};
+pub use ring::{RingBuffer, Producer, Consumer};
+pub use sync::{SpinMutex, SpinMutexGuard, SpinRwLock, SpinReadGuard, SpinWriteGuard, Backoff};
+pub use arena::{ArenaAllocator, ArenaHandle, ArenaMark};
+pub use pool::{PoolAllocator, PoolHandle};
+pub use global::{GlobalAllocator, alloc_error};
+pub use boxed::CBox;
+pub use rc::{CRc, CWeak};
+pub use tracking::{TrackingAllocator, TrackingHandle, AllocStats};
+pub use checked::{CheckedAllocator, CheckedHandle, CheckError};
+pub use bytes::{ByteWriter, ByteReader, ReadError};
+pub use num::{itoa, utoa, ftoa, atoi, atou, atof};
 
-pub type WinVec<T> = CVec<T, Win32HeapAllocator>;
\ No newline at end of file
+#[cfg(windows)]
+pub type WinVec<T> = CVec<T, Win32HeapAllocator>;
+pub type SysVec<T> = CVec<T, SystemAllocator>;
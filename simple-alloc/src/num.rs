@@ -0,0 +1,314 @@
+use crate::alloc::Allocator;
+use crate::arraystring::ArrayString;
+use crate::string::CString;
+use core::convert::TryFrom;
+
+// Number formatting and parsing without `core::fmt`, which weighs a lot
+// in a size-coded binary.
+
+/// String types the formatting functions append to.
+pub trait PushStr {
+    fn push_str(&mut self, s: &str);
+}
+
+impl<A: Allocator> PushStr for CString<A> {
+    fn push_str(&mut self, s: &str) {
+        CString::push_str(self, s)
+    }
+}
+
+impl<const N: usize> PushStr for ArrayString<N> {
+    fn push_str(&mut self, s: &str) {
+        ArrayString::push_str(self, s)
+    }
+}
+
+// Exact powers of ten in f64
+const POW10: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11,
+    1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+
+/// Digits shown at most after the decimal point, more can't be represented in a u64.
+pub const MAX_PRECISION: usize = 18;
+
+fn push_digits<S: PushStr>(out: &mut S, mut value: u64, min_digits: usize) {
+    let mut buf = [b'0'; 20];
+    let mut start = buf.len();
+    while value > 0 || buf.len() - start < min_digits.max(1) {
+        start -= 1;
+        buf[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+    out.push_str(unsafe { core::str::from_utf8_unchecked(&buf[start..]) });
+}
+
+pub fn utoa<S: PushStr>(out: &mut S, value: u64) {
+    push_digits(out, value, 1);
+}
+
+pub fn itoa<S: PushStr>(out: &mut S, value: i64) {
+    if value < 0 {
+        out.push_str("-");
+    }
+    push_digits(out, value.unsigned_abs(), 1);
+}
+
+/// Fixed-point formatting with `precision` decimals, rounded half away from zero.
+/// Values too large for a u64 are written in scientific notation, `1.500e25`.
+pub fn ftoa<S: PushStr>(out: &mut S, value: f64, precision: usize) {
+    let precision = precision.min(MAX_PRECISION);
+    if value.is_nan() {
+        out.push_str("NaN");
+        return;
+    }
+    if value.is_sign_negative() {
+        out.push_str("-");
+    }
+    let value = value.abs();
+    if value.is_infinite() {
+        out.push_str("inf");
+        return;
+    }
+
+    if value >= 1e18 {
+        // No log10 in core, the normalization loop is fine for debug output
+        let mut mantissa = value;
+        let mut exponent = 0;
+        while mantissa >= 10.0 {
+            mantissa /= 10.0;
+            exponent += 1;
+        }
+        ftoa(out, mantissa, precision);
+        out.push_str("e");
+        utoa(out, exponent);
+        return;
+    }
+
+    let scale = 10u64.pow(precision as u32);
+    let mut integer = value as u64;
+    let mut fraction = ((value - integer as f64) * scale as f64 + 0.5) as u64;
+    if fraction >= scale {
+        integer += 1;
+        fraction -= scale;
+    }
+
+    utoa(out, integer);
+    if precision > 0 {
+        out.push_str(".");
+        push_digits(out, fraction, precision);
+    }
+}
+
+/// Parses an unsigned decimal integer, `None` on anything else or on overflow.
+pub fn atou(s: &str) -> Option<u64> {
+    let s = s.strip_prefix('+').unwrap_or(s);
+    if s.is_empty() {
+        return None;
+    }
+
+    let mut value: u64 = 0;
+    for &b in s.as_bytes() {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        value = value.checked_mul(10)?.checked_add((b - b'0') as u64)?;
+    }
+    Some(value)
+}
+
+/// Parses a signed decimal integer, `None` on anything else or on overflow.
+pub fn atoi(s: &str) -> Option<i64> {
+    match s.strip_prefix('-') {
+        Some(digits) if !digits.starts_with('+') => {
+            let value = atou(digits)?;
+            if value == i64::MIN.unsigned_abs() {
+                Some(i64::MIN)
+            } else {
+                i64::try_from(value).ok().map(|v| -v)
+            }
+        }
+        Some(_) => None,
+        None => atou(s).and_then(|v| i64::try_from(v).ok()),
+    }
+}
+
+// Scales by 10^exponent in exact power of ten steps. Negative exponents divide,
+// the reciprocal of a large power would overflow to infinity
+fn scale10(mut value: f64, exponent: i32) -> f64 {
+    let mut remaining = exponent.unsigned_abs() as usize;
+    while remaining > 0 {
+        let step = remaining.min(POW10.len() - 1);
+        if exponent < 0 {
+            value /= POW10[step];
+        } else {
+            value *= POW10[step];
+        }
+        remaining -= step;
+    }
+    value
+}
+
+/// Parses `[+-]digits[.digits][(e|E)[+-]digits]`, `inf` and `NaN`.
+/// Exact when the digits fit in 2^53 and the decimal exponent stays within 22,
+/// which covers what we write by hand. Other values can be off by a few ulps.
+pub fn atof(s: &str) -> Option<f64> {
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let sign = if negative { -1.0 } else { 1.0 };
+    match s {
+        "inf" => return Some(sign * f64::INFINITY),
+        "NaN" | "nan" => return Some(f64::NAN),
+        _ => {}
+    }
+
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    let mut mantissa: u64 = 0;
+    let mut exponent: i32 = 0;
+    let mut digits = 0;
+    let mut seen_point = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b @ b'0'..=b'9' => {
+                digits += 1;
+                // Digits past what a u64 holds only move the exponent
+                if mantissa < (u64::MAX - 9) / 10 {
+                    mantissa = mantissa * 10 + (b - b'0') as u64;
+                    if seen_point {
+                        exponent -= 1;
+                    }
+                } else if !seen_point {
+                    exponent += 1;
+                }
+            }
+            b'.' if !seen_point => seen_point = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    if digits == 0 {
+        return None;
+    }
+
+    if i < bytes.len() {
+        if bytes[i] != b'e' && bytes[i] != b'E' {
+            return None;
+        }
+        let explicit = atoi(&s[i + 1..])?;
+        exponent = exponent.saturating_add(explicit.clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+    }
+
+    // Past this any u64 mantissa is already zero or infinite
+    let exponent = exponent.clamp(-400, 400);
+    Some(sign * scale10(mantissa as f64, exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int_text(value: i64) -> ArrayString<24> {
+        let mut text = ArrayString::new();
+        itoa(&mut text, value);
+        text
+    }
+
+    fn float_text(value: f64, precision: usize) -> ArrayString<48> {
+        let mut text = ArrayString::new();
+        ftoa(&mut text, value, precision);
+        text
+    }
+
+    #[test]
+    fn test_itoa() {
+        assert_eq!(int_text(0), "0");
+        assert_eq!(int_text(-42), "-42");
+        assert_eq!(int_text(i64::MIN), "-9223372036854775808");
+        let mut text: CString = CString::from_str("frame ");
+        utoa(&mut text, u64::MAX);
+        assert_eq!(text, "frame 18446744073709551615");
+
+        for &v in &[0, 1, -1, 9, 10, -99, 1 << 40, i64::MAX, i64::MIN, i64::MIN + 1] {
+            assert_eq!(atoi(&int_text(v)), Some(v));
+        }
+        assert_eq!(atou("18446744073709551615"), Some(u64::MAX));
+        assert_eq!(atou("18446744073709551616"), None);
+        assert_eq!(atoi("9223372036854775808"), None);
+        assert_eq!(atoi("+17"), Some(17));
+        for &s in &["", "-", "+", "-+1", "+-1", "1 ", "0x10", "1_000"] {
+            assert_eq!(atoi(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_ftoa() {
+        assert_eq!(float_text(1.23456, 2), "1.23");
+        assert_eq!(float_text(2.5, 0), "3");
+        assert_eq!(float_text(-0.0005, 3), "-0.001");
+        assert_eq!(float_text(9.9999, 3), "10.000");
+        assert_eq!(float_text(0.05, 1), "0.1");
+        assert_eq!(float_text(1.5e25, 3), "1.500e25");
+        assert_eq!(float_text(f64::NAN, 3), "NaN");
+        assert_eq!(float_text(f64::NEG_INFINITY, 3), "-inf");
+        assert_eq!(float_text(1.0, 40), "1.000000000000000000");
+    }
+
+    #[test]
+    fn test_atof() {
+        assert_eq!(atof("1.23456"), Some(1.23456));
+        assert_eq!(atof("-0.1"), Some(-0.1));
+        assert_eq!(atof("1e-3"), Some(0.001));
+        assert_eq!(atof("2.5E2"), Some(250.0));
+        assert_eq!(atof("+.5"), Some(0.5));
+        assert_eq!(atof("7."), Some(7.0));
+        let long = atof("123456789012345678901234").unwrap();
+        assert!((long / 1.2345678901234568e23 - 1.0).abs() < 1e-15);
+        assert_eq!(atof("-inf"), Some(f64::NEG_INFINITY));
+        assert!(atof("NaN").unwrap().is_nan());
+        for &s in &["", "-", ".", "e5", "1e", "1.2.3", "1e+", "12a", " 1"] {
+            assert_eq!(atof(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_atof_extremes() {
+        assert_eq!(atof("2.2250738585072014e-308"), Some(f64::MIN_POSITIVE));
+        assert_eq!(atof("-2.2250738585072014e-308"), Some(-f64::MIN_POSITIVE));
+        for &(s, v) in &[
+            ("2.2250738585072009e-308", 2.225073858507201e-308),
+            ("1.2345678901234567e-300", 1.2345678901234567e-300),
+            ("1.7976931348623157e308", f64::MAX),
+        ] {
+            let parsed = atof(s).unwrap();
+            assert!((parsed / v - 1.0).abs() < 1e-15, "{} -> {:e}", s, parsed);
+        }
+        // Subnormals
+        assert_eq!(atof("5e-324"), Some(5e-324));
+        assert_eq!(atof("1e-320"), Some(1e-320));
+        // Huge exponents don't loop for long
+        assert_eq!(atof("1e2000000000"), Some(f64::INFINITY));
+        assert_eq!(atof("1e-2000000000"), Some(0.0));
+        assert_eq!(atof("0e999999"), Some(0.0));
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        let values = [0.0, 1.0, -1.0, 0.1, 0.333333, 123.456, -98765.4321, 1e-7, 6.02214076e17, 44100.0 / 3.0];
+        for (precision, &scale) in POW10.iter().enumerate().take(10) {
+            let tolerance = 0.5 / scale * (1.0 + 1e-9);
+            for &v in &values {
+                let text = float_text(v, precision);
+                let back = atof(&text).unwrap();
+                assert!((back - v).abs() <= tolerance.max(v.abs() * 1e-15), "{} -> {} -> {}", v, text, back);
+            }
+        }
+        // Short decimals written back at full precision come back unchanged
+        for &v in &[0.1, 0.25, -3.75, 1234.5678, 1e-6] {
+            assert_eq!(atof(&float_text(v, 9)), Some(v));
+        }
+    }
+}
@@ -1,6 +1,11 @@
 
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
 use core::hint::unreachable_unchecked as unreachable;
-use core::sync::atomic::{spin_loop_hint as cpu_relax, AtomicUsize, Ordering};
+use core::hint::spin_loop as cpu_relax;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 const INCOMPLETE: usize = 0x0;
 const RUNNING: usize = 0x1;
@@ -14,26 +19,46 @@ pub struct Once {
 unsafe impl Sync for Once {}
 unsafe impl Send for Once {}
 
+impl Default for Once {
+    fn default() -> Self {
+        Self::INIT
+    }
+}
+
 impl Once {
+    #[allow(clippy::declare_interior_mutable_const)]
     pub const INIT: Self = Once {
         state: AtomicUsize::new(INCOMPLETE),
     };
 
-    pub fn new() -> Once {
+    pub const fn new() -> Once {
         Self::INIT
     }
 
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == COMPLETE
+    }
+
+    /// True if an initializer panicked, every later `run_once` will panic too.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == PANICKED
+    }
+
     pub fn run_once<F: FnOnce()>(&self, initializer: F) {
         let mut status = self.state.load(Ordering::SeqCst);
 
         if status == INCOMPLETE {
-            status = self
+            status = match self
                 .state
-                .compare_and_swap(INCOMPLETE, RUNNING, Ordering::SeqCst);
+                .compare_exchange(INCOMPLETE, RUNNING, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(previous) | Err(previous) => previous,
+            };
             if status == INCOMPLETE {
+                let mut finish = Finish { state: &self.state, panicked: true };
                 initializer();
-                status = COMPLETE;
-                self.state.store(status, Ordering::SeqCst);
+                finish.panicked = false;
+                return;
             }
         }
 
@@ -45,17 +70,182 @@ impl Once {
                     status = self.state.load(Ordering::SeqCst)
                 }
                 PANICKED => panic!("Once has panicked"),
-                COMPLETE => return (),
+                COMPLETE => return,
                 _ => unsafe { unreachable() },
             }
         }
     }
 }
 
+// Publishes the result of the initializer, even when it unwinds
+struct Finish<'a> {
+    state: &'a AtomicUsize,
+    panicked: bool,
+}
+
+impl<'a> Drop for Finish<'a> {
+    fn drop(&mut self) {
+        let status = if self.panicked { PANICKED } else { COMPLETE };
+        self.state.store(status, Ordering::SeqCst);
+    }
+}
+
+/// Cell written once, usable in statics:
+///
+/// ```ignore
+/// static CONFIG: OnceCell<Config> = OnceCell::new();
+/// let config = CONFIG.get_or_init(Config::load);
+/// ```
+pub struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            Some(unsafe { &*(*self.value.get()).as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            Some(unsafe { &mut *self.value.get_mut().as_mut_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Runs `f` if the cell is empty, other threads wait for it to finish.
+    /// Panics if `f` panicked in an earlier call.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        self.once.run_once(|| unsafe {
+            (*self.value.get()).as_mut_ptr().write(f());
+        });
+        unsafe { &*(*self.value.get()).as_ptr() }
+    }
+
+    /// Gives `value` back if the cell was already initialized.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.once.is_poisoned()
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        let value = self.take();
+        core::mem::forget(self);
+        value
+    }
+
+    fn take(&mut self) -> Option<T> {
+        if self.once.is_completed() {
+            self.once = Once::new();
+            Some(unsafe { self.value.get_mut().as_ptr().read() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        self.take();
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+/// Value computed on first access, a `static` friendly replacement for
+/// `static mut` + `Once`:
+///
+/// ```ignore
+/// static GL_CLEAR: Lazy<Option<CLEARPROC>> = Lazy::new(|| load("glClear"));
+/// ```
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: Cell<Option<F>>,
+}
+
+// `init` is only touched by the thread running the initializer
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F> Lazy<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    pub fn is_poisoned(this: &Self) -> bool {
+        this.cell.is_poisoned()
+    }
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => unreachable!(),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Lazy").field(&self.cell).finish()
+    }
+}
 
 #[cfg(test)]
 mod tests {
+    extern crate std;
+
     use super::*;
+    use crate::string::CString;
+    use std::thread;
+    use std::vec::Vec;
 
     #[test]
     fn test_once() {
@@ -64,4 +254,44 @@ mod tests {
         ONCE.run_once(|| unsafe { FUNC_PTR = 2 });
         assert_eq!(unsafe { FUNC_PTR }, 2);
     }
+
+    #[test]
+    fn test_once_cell() {
+        static CELL: OnceCell<u32> = OnceCell::new();
+        assert_eq!(CELL.get(), None);
+        assert_eq!(*CELL.get_or_init(|| 12), 12);
+        assert_eq!(*CELL.get_or_init(|| 13), 12);
+        assert_eq!(CELL.set(14), Err(14));
+
+        let cell = OnceCell::new();
+        assert!(cell.set(CString::<crate::SystemAllocator>::from_str("gl")).is_ok());
+        assert_eq!(cell.get().map(|s| s.len()), Some(2));
+        assert_eq!(cell.into_inner().map(|s| s.len()), Some(2));
+    }
+
+    #[test]
+    fn test_lazy() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: Lazy<usize> = Lazy::new(|| CALLS.fetch_add(1, Ordering::SeqCst) + 40);
+        let threads: Vec<_> = (0..4).map(|_| thread::spawn(|| *VALUE)).collect();
+        for t in threads {
+            assert_eq!(t.join().unwrap(), 40);
+        }
+        assert_eq!(*VALUE + 2, 42);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_poison() {
+        static VALUE: Lazy<u32> = Lazy::new(|| panic!("GL loader failed"));
+        assert!(thread::spawn(|| *VALUE).join().is_err());
+        assert!(Lazy::is_poisoned(&VALUE));
+        // Later callers don't spin forever
+        assert!(thread::spawn(|| *VALUE).join().is_err());
+
+        let once = Once::new();
+        let res = std::panic::catch_unwind(|| once.run_once(|| panic!()));
+        assert!(res.is_err());
+        assert!(once.is_poisoned());
+    }
 }
@@ -0,0 +1,93 @@
+use core::ffi::c_void;
+
+// Reserve-then-commit virtual memory: an address range is reserved up front
+// without using any memory, and pages are committed as they are needed.
+
+/// Granularity of `commit`. aarch64 Linux kernels can run with 4K, 16K or
+/// 64K pages, 64K is a multiple of all of them.
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+pub const PAGE_SIZE: usize = 64 * 1024;
+#[cfg(not(all(target_os = "linux", target_arch = "aarch64")))]
+pub const PAGE_SIZE: usize = 4096;
+
+#[cfg(windows)]
+mod win {
+    pub const MEM_COMMIT: u32 = 0x00001000;
+    pub const MEM_RESERVE: u32 = 0x00002000;
+    pub const MEM_RELEASE: u32 = 0x00008000;
+    pub const PAGE_NOACCESS: u32 = 0x01;
+    pub const PAGE_READWRITE: u32 = 0x04;
+    pub const PAGE_EXECUTE_READ: u32 = 0x20;
+}
+
+#[cfg(target_os = "linux")]
+use crate::system::linux;
+
+/// Rounds `size` up to a whole number of pages.
+pub fn page_align(size: usize) -> usize {
+    size.checked_add(PAGE_SIZE - 1).expect("Capacity overflow") & !(PAGE_SIZE - 1)
+}
+
+/// Reserves `size` bytes of address space, inaccessible until committed.
+///
+/// # Safety
+/// The range must be freed with `release`.
+pub unsafe fn reserve(size: usize) -> Option<*mut c_void> {
+    #[cfg(windows)]
+    {
+        let ptr = win32::VirtualAlloc(core::ptr::null_mut(), page_align(size), win::MEM_RESERVE, win::PAGE_NOACCESS);
+        if ptr.is_null() { None } else { Some(ptr as *mut c_void) }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::mmap_reserve(page_align(size))
+    }
+}
+
+/// Makes `size` bytes from `ptr` readable and writable, zero filled.
+///
+/// # Safety
+/// `ptr` must be page aligned and the range inside one returned by `reserve`.
+pub unsafe fn commit(ptr: *mut c_void, size: usize) -> bool {
+    #[cfg(windows)]
+    {
+        !win32::VirtualAlloc(ptr as win32::LPVOID, size, win::MEM_COMMIT, win::PAGE_READWRITE).is_null()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::mprotect(ptr, page_align(size), linux::PROT_READ | linux::PROT_WRITE)
+    }
+}
+
+/// Makes `size` bytes from `ptr` readable and executable, no longer writable.
+///
+/// # Safety
+/// `ptr` must be page aligned and the range committed, nothing may write to it afterwards.
+pub unsafe fn protect_exec(ptr: *mut c_void, size: usize) -> bool {
+    #[cfg(windows)]
+    {
+        let mut old: win32::DWORD = 0;
+        win32::VirtualProtect(ptr as win32::LPVOID, size, win::PAGE_EXECUTE_READ, &mut old) != 0
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::mprotect(ptr, page_align(size), linux::PROT_READ | linux::PROT_EXEC)
+    }
+}
+
+/// Frees a whole range returned by `reserve`.
+///
+/// # Safety
+/// `ptr` and `size` must be the ones given to and returned by `reserve`,
+/// and nothing may use the range afterwards.
+pub unsafe fn release(ptr: *mut c_void, size: usize) {
+    #[cfg(windows)]
+    {
+        let _ = size;
+        win32::VirtualFree(ptr as win32::LPVOID, 0, win::MEM_RELEASE);
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::munmap(ptr, page_align(size));
+    }
+}
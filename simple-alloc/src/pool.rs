@@ -0,0 +1,246 @@
+use crate::alloc::*;
+use core::cell::{Cell, UnsafeCell};
+use core::cmp::max;
+use core::ffi::c_void;
+use core::mem;
+use core::ptr;
+
+// Free blocks store the next free block in their first word
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+/// Allocator of fixed size blocks, for the many same-sized objects of
+/// particle systems and scene graphs. Blocks are carved from chunks of
+/// `blocks_per_chunk` blocks and recycled through an intrusive free list,
+/// so alloc and dealloc are O(1). A full pool fails allocations unless
+/// `set_growable(true)` lets it chain a new chunk.
+pub struct PoolAllocator<A: Allocator> {
+    block: Layout,
+    blocks_per_chunk: usize,
+    growable: bool,
+    free: Cell<*mut FreeBlock>,
+    // Chunks are linked through their first word, most recent first
+    chunks: Cell<*mut u8>,
+    chunk_count: Cell<usize>,
+    live: Cell<usize>,
+    alloc: UnsafeCell<A>,
+}
+
+impl<A: Allocator + Default> PoolAllocator<A> {
+    pub fn with_capacity(block: Layout, blocks_per_chunk: usize) -> Self {
+        Self::with_capacity_in(block, blocks_per_chunk, A::default())
+    }
+
+    /// Pool of blocks fitting a `T`.
+    pub fn for_type<T>(blocks_per_chunk: usize) -> Self {
+        Self::with_capacity(Layout::from_type::<T>(), blocks_per_chunk)
+    }
+}
+
+impl<A: Allocator> PoolAllocator<A> {
+    pub fn with_capacity_in(block: Layout, blocks_per_chunk: usize, alloc: A) -> Self {
+        assert!(blocks_per_chunk > 0, "Pool chunks need at least one block");
+        // Free blocks must be able to hold the free list link
+        let align = max(block.align, mem::align_of::<FreeBlock>());
+        let size = max(block.size, mem::size_of::<FreeBlock>());
+        let pool = Self {
+            block: Layout::new((size + align - 1) & !(align - 1), align),
+            blocks_per_chunk,
+            growable: false,
+            free: Cell::new(ptr::null_mut()),
+            chunks: Cell::new(ptr::null_mut()),
+            chunk_count: Cell::new(0),
+            live: Cell::new(0),
+            alloc: UnsafeCell::new(alloc),
+        };
+        pool.add_chunk().expect("Allocation error");
+        pool
+    }
+
+    pub fn set_growable(&mut self, growable: bool) {
+        self.growable = growable;
+    }
+
+    /// Allocator to give to a `CBox`, `CVec`, ... The borrow keeps the pool
+    /// alive while containers using it are.
+    pub fn handle(&self) -> PoolHandle<'_, A> {
+        PoolHandle { pool: self }
+    }
+
+    /// Size of the blocks handed out, requests up to this size are accepted.
+    pub fn block_size(&self) -> usize {
+        self.block.size
+    }
+
+    pub fn live(&self) -> usize {
+        self.live.get()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.chunk_count.get() * self.blocks_per_chunk
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_count.get()
+    }
+
+    // The chunk link takes a whole block to keep the blocks aligned
+    fn chunk_layout(&self) -> Layout {
+        Layout::new(self.block.size * (self.blocks_per_chunk + 1), self.block.align)
+    }
+
+    fn add_chunk(&self) -> Option<()> {
+        unsafe {
+            let chunk = (*self.alloc.get()).alloc(self.chunk_layout())? as *mut u8;
+            (chunk as *mut *mut u8).write(self.chunks.get());
+            self.chunks.set(chunk);
+            self.chunk_count.set(self.chunk_count.get() + 1);
+
+            // Link the new blocks in address order
+            for i in (1..=self.blocks_per_chunk).rev() {
+                let block = chunk.add(i * self.block.size) as *mut FreeBlock;
+                block.write(FreeBlock { next: self.free.get() });
+                self.free.set(block);
+            }
+        }
+        Some(())
+    }
+
+    fn fits(&self, layout: Layout) -> bool {
+        layout.size <= self.block.size && layout.align <= self.block.align
+    }
+
+    fn pop(&self, layout: Layout) -> Option<*mut c_void> {
+        if !self.fits(layout) {
+            return None;
+        }
+        if self.free.get().is_null() {
+            if !self.growable {
+                return None;
+            }
+            self.add_chunk()?;
+        }
+
+        let block = self.free.get();
+        self.free.set(unsafe { (*block).next });
+        self.live.set(self.live.get() + 1);
+        Some(block as *mut c_void)
+    }
+
+    fn push(&self, ptr: *mut c_void) {
+        let block = ptr as *mut FreeBlock;
+        unsafe { block.write(FreeBlock { next: self.free.get() }) };
+        self.free.set(block);
+        self.live.set(self.live.get() - 1);
+    }
+}
+
+impl<A: Allocator> Drop for PoolAllocator<A> {
+    fn drop(&mut self) {
+        let layout = self.chunk_layout();
+        let mut chunk = self.chunks.get();
+        while !chunk.is_null() {
+            unsafe {
+                let next = (chunk as *mut *mut u8).read();
+                self.alloc.get_mut().dealloc(chunk as *mut c_void, layout);
+                chunk = next;
+            }
+        }
+    }
+}
+
+pub struct PoolHandle<'a, A: Allocator> {
+    pool: &'a PoolAllocator<A>,
+}
+
+impl<'a, A: Allocator> Clone for PoolHandle<'a, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, A: Allocator> Copy for PoolHandle<'a, A> {}
+
+impl<'a, A: Allocator> Allocator for PoolHandle<'a, A> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        self.pool.pop(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, _layout: Layout) {
+        self.pool.push(ptr)
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut c_void, _layout: Layout, new_size: usize) -> Option<*mut c_void> {
+        // Every block has the same size, the request either fits or fails
+        if new_size <= self.pool.block.size {
+            Some(ptr)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boxed::CBox;
+    use crate::system::SystemAllocator;
+    use crate::vec::CVec;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    struct Particle {
+        position: [f32; 3],
+        velocity: [f32; 3],
+        life: u32,
+    }
+
+    type Pool = PoolAllocator<SystemAllocator>;
+
+    #[test]
+    fn test_pool_alloc() {
+        let pool = Pool::with_capacity(Layout::new(24, 8), 4);
+        assert_eq!(pool.block_size(), 24);
+        let mut handle = pool.handle();
+        unsafe {
+            let blocks: CVec<*mut c_void, SystemAllocator> = (0..4).fold(CVec::new(), |mut v, _| {
+                v.push(handle.alloc(Layout::new(20, 4)).unwrap());
+                v
+            });
+            for pair in blocks.windows(2) {
+                assert_eq!(pair[1] as usize - pair[0] as usize, 24);
+                assert_eq!(pair[0] as usize % 8, 0);
+            }
+            assert!(handle.alloc(Layout::new(8, 8)).is_none());
+            assert!(handle.realloc(blocks[0], Layout::new(20, 4), 32).is_none());
+            assert_eq!(pool.live(), 4);
+
+            // Freed blocks are reused first
+            handle.dealloc(blocks[2], Layout::new(20, 4));
+            assert_eq!(handle.alloc(Layout::new(24, 8)), Some(blocks[2]));
+            assert!(handle.alloc(Layout::new(48, 8)).is_none());
+            assert!(handle.alloc(Layout::new(8, 16)).is_none());
+        }
+        assert_eq!(pool.capacity(), 4);
+    }
+
+    #[test]
+    fn test_pool_grow() {
+        let mut pool = Pool::for_type::<Particle>(16);
+        pool.set_growable(true);
+        {
+            let mut particles: CVec<CBox<Particle, _>, SystemAllocator> = CVec::new();
+            for i in 0..40 {
+                let particle = Particle { position: [i as f32; 3], velocity: [0.0; 3], life: i };
+                particles.push(CBox::new_in(particle, pool.handle()));
+            }
+            assert_eq!(pool.chunk_count(), 3);
+            assert_eq!(pool.live(), 40);
+            particles.retain(|p| p.life % 2 == 0);
+            assert_eq!(pool.live(), 20);
+            assert_eq!(particles[10].position, [20.0; 3]);
+        }
+        assert_eq!(pool.live(), 0);
+        assert_eq!(pool.capacity(), 48);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::alloc::*;
+use core::cell::Cell;
+use core::ffi::c_void;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+
+// The allocator lives next to the counters so every clone can free the block
+struct RcBox<T, A: Allocator> {
+    strong: Cell<usize>,
+    // Weak references, plus one shared by all the strong ones
+    weak: Cell<usize>,
+    alloc: A,
+    value: T,
+}
+
+/// Single-threaded reference counted pointer, generic over the allocator.
+pub struct CRc<T, A: Allocator> {
+    ptr: NonNull<RcBox<T, A>>,
+    _phantom: PhantomData<RcBox<T, A>>,
+}
+
+/// Non-owning reference to a `CRc` value, see `CRc::downgrade`.
+pub struct CWeak<T, A: Allocator> {
+    ptr: NonNull<RcBox<T, A>>,
+}
+
+impl<T, A: Allocator + Default> CRc<T, A> {
+    pub fn new(value: T) -> Self {
+        Self::new_in(value, A::default())
+    }
+}
+
+impl<T, A: Allocator> CRc<T, A> {
+    pub fn new_in(value: T, mut alloc: A) -> Self {
+        let ptr = unsafe {
+            alloc.alloc(Layout::from_type::<RcBox<T, A>>()).expect("Allocation error") as *mut RcBox<T, A>
+        };
+
+        unsafe {
+            ptr.write(RcBox {
+                strong: Cell::new(1),
+                weak: Cell::new(1),
+                alloc,
+                value,
+            });
+        }
+
+        Self {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn downgrade(this: &Self) -> CWeak<T, A> {
+        let inner = this.inner();
+        inner.weak.set(inner.weak.get() + 1);
+        CWeak { ptr: this.ptr }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+    pub fn weak_count(this: &Self) -> usize {
+        this.inner().weak.get() - 1
+    }
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Mutable access to the value if no other `CRc` or `CWeak` points to it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Self::strong_count(this) == 1 && Self::weak_count(this) == 0 {
+            Some(unsafe { &mut (*this.ptr.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+
+    /// Gives back the value if this is the only strong reference.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if Self::strong_count(&this) != 1 {
+            return Err(this);
+        }
+
+        let this = mem::ManuallyDrop::new(this);
+        let inner = this.inner();
+        inner.strong.set(0);
+        let value = unsafe { ptr::read(&inner.value) };
+        // Release the weak reference held by the strong ones
+        release_weak(this.ptr);
+        Ok(value)
+    }
+
+    fn inner(&self) -> &RcBox<T, A> {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+fn release_weak<T, A: Allocator>(ptr: NonNull<RcBox<T, A>>) {
+    unsafe {
+        let inner = ptr.as_ref();
+        inner.weak.set(inner.weak.get() - 1);
+        if inner.weak.get() == 0 {
+            let mut alloc = ptr::read(&inner.alloc);
+            alloc.dealloc(ptr.as_ptr() as *mut c_void, Layout::from_type::<RcBox<T, A>>());
+        }
+    }
+}
+
+impl<T, A: Allocator> Clone for CRc<T, A> {
+    fn clone(&self) -> Self {
+        let inner = self.inner();
+        inner.strong.set(inner.strong.get() + 1);
+        Self {
+            ptr: self.ptr,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Allocator> Drop for CRc<T, A> {
+    fn drop(&mut self) {
+        let strong = self.inner().strong.get() - 1;
+        self.inner().strong.set(strong);
+        if strong == 0 {
+            unsafe {
+                ptr::drop_in_place(ptr::addr_of_mut!((*self.ptr.as_ptr()).value));
+            }
+            release_weak(self.ptr);
+        }
+    }
+}
+
+impl<T, A: Allocator> Deref for CRc<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for CRc<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T, A: Allocator> CWeak<T, A> {
+    pub fn upgrade(&self) -> Option<CRc<T, A>> {
+        let inner = unsafe { self.ptr.as_ref() };
+        if inner.strong.get() == 0 {
+            return None;
+        }
+
+        inner.strong.set(inner.strong.get() + 1);
+        Some(CRc {
+            ptr: self.ptr,
+            _phantom: PhantomData,
+        })
+    }
+
+    pub fn strong_count(&self) -> usize {
+        unsafe { self.ptr.as_ref() }.strong.get()
+    }
+}
+
+impl<T, A: Allocator> Clone for CWeak<T, A> {
+    fn clone(&self) -> Self {
+        let inner = unsafe { self.ptr.as_ref() };
+        inner.weak.set(inner.weak.get() + 1);
+        Self { ptr: self.ptr }
+    }
+}
+
+impl<T, A: Allocator> Drop for CWeak<T, A> {
+    fn drop(&mut self) {
+        release_weak(self.ptr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::SystemAllocator;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_rc() {
+        let drops = Cell::new(0);
+        let first: CRc<DropCounter, SystemAllocator> = CRc::new(DropCounter(&drops));
+        let second = first.clone();
+        assert_eq!(CRc::strong_count(&first), 2);
+        assert!(CRc::ptr_eq(&first, &second));
+        drop(first);
+        assert_eq!(drops.get(), 0);
+        drop(second);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_rc_weak() {
+        let drops = Cell::new(0);
+        let strong: CRc<DropCounter, SystemAllocator> = CRc::new(DropCounter(&drops));
+        let weak = CRc::downgrade(&strong);
+        let weak2 = weak.clone();
+        assert_eq!(CRc::weak_count(&strong), 2);
+        {
+            let upgraded = weak.upgrade().unwrap();
+            assert_eq!(CRc::strong_count(&upgraded), 2);
+        }
+        drop(strong);
+        assert_eq!(drops.get(), 1);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak2.strong_count(), 0);
+    }
+
+    #[test]
+    fn test_rc_unique() {
+        let mut rc: CRc<u32, SystemAllocator> = CRc::new(5);
+        *CRc::get_mut(&mut rc).unwrap() += 1;
+        let other = rc.clone();
+        assert!(CRc::get_mut(&mut rc).is_none());
+        let rc = CRc::try_unwrap(rc).unwrap_err();
+        drop(other);
+        let weak = CRc::downgrade(&rc);
+        assert_eq!(CRc::try_unwrap(rc).ok(), Some(6));
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,368 @@
+use crate::alloc::*;
+use core::cmp::min;
+use core::ffi::c_void;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Keeps the producer and consumer indices on separate cache lines
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Fixed capacity single-producer/single-consumer queue, lock-free and
+/// allocation-free once created, for the synth thread feeding the audio
+/// callback or `wnd_proc` feeding the main loop:
+///
+/// ```ignore
+/// let mut ring: RingBuffer<f32, SystemAllocator> = RingBuffer::with_capacity(4096);
+/// let (mut producer, mut consumer) = ring.split();
+/// ```
+///
+/// The capacity is rounded up to a power of two.
+pub struct RingBuffer<T, A: Allocator> {
+    // Both indices only grow, wrapping, and are masked on access
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    buffer: *mut T,
+    capacity: usize,
+    alloc: A,
+    _phantom: PhantomData<T>,
+}
+
+unsafe impl<T: Send, A: Allocator + Send> Send for RingBuffer<T, A> {}
+
+impl<T, A: Allocator + Default> RingBuffer<T, A> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, A::default())
+    }
+}
+
+impl<T, A: Allocator> RingBuffer<T, A> {
+    pub fn with_capacity_in(capacity: usize, mut alloc: A) -> Self {
+        let capacity = capacity.max(1).checked_next_power_of_two().expect("Capacity overflow");
+        let buffer = unsafe { alloc.alloc(Layout::from_array_type::<T>(capacity)).expect("Allocation error") as *mut T };
+        Self {
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            buffer,
+            capacity,
+            alloc,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Splits the queue in its two ends, each one can be moved to its own thread.
+    pub fn split(&mut self) -> (Producer<'_, T, A>, Consumer<'_, T, A>) {
+        let ring: &Self = self;
+        (Producer { ring }, Consumer { ring })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity
+    }
+
+    fn slot(&self, index: usize) -> *mut T {
+        unsafe { self.buffer.add(index & (self.capacity - 1)) }
+    }
+}
+
+impl<T, A: Allocator> Drop for RingBuffer<T, A> {
+    fn drop(&mut self) {
+        let tail = *self.tail.0.get_mut();
+        let mut head = *self.head.0.get_mut();
+        while head != tail {
+            unsafe { ptr::drop_in_place(self.slot(head)) };
+            head = head.wrapping_add(1);
+        }
+        unsafe {
+            self.alloc.dealloc(self.buffer as *mut c_void, Layout::from_array_type::<T>(self.capacity));
+        }
+    }
+}
+
+impl<T, A: Allocator> fmt::Debug for RingBuffer<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RingBuffer")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+/// Writing end of a `RingBuffer`.
+pub struct Producer<'a, T, A: Allocator> {
+    ring: &'a RingBuffer<T, A>,
+}
+
+unsafe impl<'a, T: Send, A: Allocator> Send for Producer<'a, T, A> {}
+
+impl<'a, T, A: Allocator> Producer<'a, T, A> {
+    /// Hands the value back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.ring.capacity {
+            return Err(value);
+        }
+
+        unsafe { self.ring.slot(tail).write(value) };
+        self.ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn free_len(&self) -> usize {
+        self.ring.capacity - self.ring.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.free_len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity
+    }
+}
+
+impl<'a, T: Copy, A: Allocator> Producer<'a, T, A> {
+    /// Pushes as much of `values` as fits, returns how many were pushed.
+    pub fn push_slice(&mut self, values: &[T]) -> usize {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        let count = min(values.len(), self.ring.capacity - tail.wrapping_sub(head));
+
+        // The free space may wrap around the end of the buffer
+        let start = tail & (self.ring.capacity - 1);
+        let first = min(count, self.ring.capacity - start);
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), self.ring.buffer.add(start), first);
+            ptr::copy_nonoverlapping(values.as_ptr().add(first), self.ring.buffer, count - first);
+        }
+        self.ring.tail.store(tail.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+/// Reading end of a `RingBuffer`.
+pub struct Consumer<'a, T, A: Allocator> {
+    ring: &'a RingBuffer<T, A>,
+}
+
+unsafe impl<'a, T: Send, A: Allocator> Send for Consumer<'a, T, A> {}
+
+impl<'a, T, A: Allocator> Consumer<'a, T, A> {
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe { self.ring.slot(head).read() };
+        self.ring.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Next value to pop, left in the queue.
+    pub fn peek(&self) -> Option<&T> {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            None
+        } else {
+            Some(unsafe { &*self.ring.slot(head) })
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, T: Copy, A: Allocator> Consumer<'a, T, A> {
+    /// Fills as much of `values` as possible, returns how many were popped.
+    pub fn pop_slice(&mut self, values: &mut [T]) -> usize {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        let count = min(values.len(), tail.wrapping_sub(head));
+
+        let start = head & (self.ring.capacity - 1);
+        let first = min(count, self.ring.capacity - start);
+        unsafe {
+            ptr::copy_nonoverlapping(self.ring.buffer.add(start), values.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(self.ring.buffer, values.as_mut_ptr().add(first), count - first);
+        }
+        self.ring.head.store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::sync::yield_now;
+    use crate::system::SystemAllocator;
+    use core::cell::Cell;
+    use std::thread;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    type Ring<T> = RingBuffer<T, SystemAllocator>;
+
+    #[test]
+    fn test_ring_wraparound() {
+        let mut ring: Ring<u32> = RingBuffer::with_capacity(3);
+        assert_eq!(ring.capacity(), 4);
+        let (mut producer, mut consumer) = ring.split();
+
+        for round in 0..10 {
+            for i in 0..3 {
+                assert!(producer.push(round * 10 + i).is_ok());
+            }
+            assert_eq!(consumer.peek(), Some(&(round * 10)));
+            assert_eq!(consumer.pop(), Some(round * 10));
+            assert_eq!(consumer.pop(), Some(round * 10 + 1));
+            assert_eq!(consumer.pop(), Some(round * 10 + 2));
+            assert_eq!(consumer.pop(), None);
+        }
+
+        producer.push(1).unwrap();
+        assert_eq!(producer.push_slice(&[2, 3, 4, 5, 6]), 3);
+        assert!(producer.is_full());
+        assert_eq!(producer.push(7), Err(7));
+
+        let mut block = [0; 3];
+        assert_eq!(consumer.pop_slice(&mut block), 3);
+        assert_eq!(block, [1, 2, 3]);
+        assert_eq!(producer.push_slice(&[5, 6]), 2);
+        let mut block = [0; 8];
+        assert_eq!(consumer.pop_slice(&mut block), 3);
+        assert_eq!(&block[..3], &[4, 5, 6]);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_ring_drop() {
+        let drops = Cell::new(0);
+        {
+            let mut ring: Ring<DropCounter> = RingBuffer::with_capacity(8);
+            let (mut producer, mut consumer) = ring.split();
+            for _ in 0..5 {
+                assert!(producer.push(DropCounter(&drops)).is_ok());
+            }
+            consumer.pop();
+            assert_eq!(drops.get(), 1);
+        }
+        assert_eq!(drops.get(), 5);
+    }
+
+    #[test]
+    fn test_ring_threads() {
+        const COUNT: u32 = 100_000;
+        let mut ring: Ring<u32> = RingBuffer::with_capacity(64);
+        let (mut producer, mut consumer) = ring.split();
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..COUNT {
+                    let mut value = i;
+                    while let Err(v) = producer.push(value) {
+                        value = v;
+                        yield_now();
+                    }
+                }
+            });
+            s.spawn(move || {
+                let mut expected = 0;
+                while expected < COUNT {
+                    match consumer.pop() {
+                        Some(value) => {
+                            assert_eq!(value, expected);
+                            expected += 1;
+                        }
+                        None => yield_now(),
+                    }
+                }
+            });
+        });
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_ring_threads_slices() {
+        // Audio style: the synth renders blocks, the callback pulls its own block size
+        const COUNT: usize = 48_000;
+        let mut ring: Ring<f32> = RingBuffer::with_capacity(1024);
+        let (mut producer, mut consumer) = ring.split();
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                let mut sent = 0;
+                let mut block = [0.0; 256];
+                while sent < COUNT {
+                    for (i, sample) in block.iter_mut().enumerate() {
+                        *sample = (sent + i) as f32;
+                    }
+                    let len = min(block.len(), COUNT - sent);
+                    let mut pushed = 0;
+                    while pushed < len {
+                        match producer.push_slice(&block[pushed..len]) {
+                            0 => yield_now(),
+                            n => pushed += n,
+                        }
+                    }
+                    sent += len;
+                }
+            });
+            s.spawn(move || {
+                let mut received = 0;
+                let mut block = [0.0; 100];
+                while received < COUNT {
+                    let n = consumer.pop_slice(&mut block);
+                    if n == 0 {
+                        yield_now();
+                    }
+                    for (i, &sample) in block[..n].iter().enumerate() {
+                        assert_eq!(sample, (received + i) as f32);
+                    }
+                    received += n;
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,381 @@
+use crate::alloc::*;
+use crate::vec::CVec;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+use core::slice;
+
+const NO_FREE_SLOT: u32 = u32::MAX;
+
+/// Key of a `SlotMap` value. The generation tells apart the successive
+/// values stored in a slot, so a handle to a removed value stays invalid
+/// even once its slot is reused.
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+// Manual impls, the derives would require `T` to implement them
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+struct Slot {
+    generation: u32,
+    occupied: bool,
+    // Position in `values` when occupied, next free slot otherwise
+    target: u32,
+}
+
+/// Storage handing out generational `Handle`s instead of raw ids, for GPU
+/// resources and other objects that can be deleted while still referenced:
+///
+/// ```ignore
+/// let mut programs: SlotMap<u32, SystemAllocator> = SlotMap::new();
+/// let triangle = programs.insert(glCreateProgram());
+/// glUseProgram(programs[triangle]);
+/// ```
+///
+/// Values are kept packed, so iteration is as fast as over a `CVec`, and
+/// removal moves the last value in the hole.
+pub struct SlotMap<T, A: Allocator + Clone> {
+    slots: CVec<Slot, A>,
+    values: CVec<T, A>,
+    // Slot of each value
+    value_slots: CVec<u32, A>,
+    free_head: u32,
+}
+
+impl<T, A: Allocator + Clone + Default> SlotMap<T, A> {
+    pub fn new() -> Self {
+        Self::new_in(A::default())
+    }
+}
+
+impl<T, A: Allocator + Clone + Default> Default for SlotMap<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A: Allocator + Clone> SlotMap<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self {
+            slots: CVec::new_in(alloc.clone()),
+            values: CVec::new_in(alloc.clone()),
+            value_slots: CVec::new_in(alloc),
+            free_head: NO_FREE_SLOT,
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        let position = self.values.len() as u32;
+        let index = if self.free_head != NO_FREE_SLOT {
+            let index = self.free_head;
+            let slot = &mut self.slots[index as usize];
+            self.free_head = slot.target;
+            slot.occupied = true;
+            slot.target = position;
+            index
+        } else {
+            assert!(self.slots.len() < NO_FREE_SLOT as usize, "SlotMap is full");
+            self.slots.push(Slot { generation: 0, occupied: true, target: position });
+            self.slots.len() as u32 - 1
+        };
+
+        self.values.push(value);
+        self.value_slots.push(index);
+        Handle {
+            index,
+            generation: self.slots[index as usize].generation,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns `None` if the handle is stale.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        let position = self.position(handle)?;
+        let slot = &mut self.slots[handle.index as usize];
+        slot.occupied = false;
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.target = self.free_head;
+        self.free_head = handle.index;
+
+        let value = self.values.swap_remove(position);
+        self.value_slots.swap_remove(position);
+        // Point the slot of the moved value to its new position
+        if position < self.values.len() {
+            let moved = self.value_slots[position];
+            self.slots[moved as usize].target = position as u32;
+        }
+        Some(value)
+    }
+
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.position(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        let position = self.position(handle)?;
+        Some(&self.values[position])
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        let position = self.position(handle)?;
+        Some(&mut self.values[position])
+    }
+
+    /// Removes every value, all handles given so far become stale.
+    pub fn clear(&mut self) {
+        while let Some(&index) = self.value_slots.last() {
+            let generation = self.slots[index as usize].generation;
+            self.remove(Handle { index, generation, _phantom: PhantomData });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Values in storage order, which changes when values are removed.
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    pub fn iter(&self) -> SlotMapIter<'_, T> {
+        SlotMapIter {
+            slots: &self.slots,
+            values: self.values.iter(),
+            value_slots: self.value_slots.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> SlotMapIterMut<'_, T> {
+        SlotMapIterMut {
+            slots: &self.slots,
+            values: self.values.iter_mut(),
+            value_slots: self.value_slots.iter(),
+        }
+    }
+
+    fn position(&self, handle: Handle<T>) -> Option<usize> {
+        match self.slots.get(handle.index as usize) {
+            Some(slot) if slot.occupied && slot.generation == handle.generation => Some(slot.target as usize),
+            _ => None,
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> Index<Handle<T>> for SlotMap<T, A> {
+    type Output = T;
+
+    fn index(&self, handle: Handle<T>) -> &T {
+        match self.get(handle) {
+            Some(value) => value,
+            None => panic!("Stale handle {:?}", handle),
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> IndexMut<Handle<T>> for SlotMap<T, A> {
+    fn index_mut(&mut self, handle: Handle<T>) -> &mut T {
+        match self.get_mut(handle) {
+            Some(value) => value,
+            None => panic!("Stale handle {:?}", handle),
+        }
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator + Clone> fmt::Debug for SlotMap<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+fn handle_of<T>(slots: &[Slot], index: u32) -> Handle<T> {
+    Handle {
+        index,
+        generation: slots[index as usize].generation,
+        _phantom: PhantomData,
+    }
+}
+
+pub struct SlotMapIter<'a, T> {
+    slots: &'a [Slot],
+    values: slice::Iter<'a, T>,
+    value_slots: slice::Iter<'a, u32>,
+}
+
+impl<'a, T> Iterator for SlotMapIter<'a, T> {
+    type Item = (Handle<T>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = self.values.next()?;
+        let &index = self.value_slots.next()?;
+        Some((handle_of(self.slots, index), value))
+    }
+}
+
+pub struct SlotMapIterMut<'a, T> {
+    slots: &'a [Slot],
+    values: slice::IterMut<'a, T>,
+    value_slots: slice::Iter<'a, u32>,
+}
+
+impl<'a, T> Iterator for SlotMapIterMut<'a, T> {
+    type Item = (Handle<T>, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = self.values.next()?;
+        let &index = self.value_slots.next()?;
+        Some((handle_of(self.slots, index), value))
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> IntoIterator for &'a SlotMap<T, A> {
+    type Item = (Handle<T>, &'a T);
+    type IntoIter = SlotMapIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> IntoIterator for &'a mut SlotMap<T, A> {
+    type Item = (Handle<T>, &'a mut T);
+    type IntoIter = SlotMapIterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::SystemAllocator;
+    use core::cell::Cell;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Mesh {
+        vao: u32,
+        vertex_count: i32,
+    }
+
+    type Meshes = SlotMap<Mesh, SystemAllocator>;
+
+    #[test]
+    fn test_slot_map() {
+        let mut meshes = Meshes::new();
+        let triangle = meshes.insert(Mesh { vao: 1, vertex_count: 3 });
+        let quad = meshes.insert(Mesh { vao: 2, vertex_count: 6 });
+        let cube = meshes.insert(Mesh { vao: 3, vertex_count: 36 });
+        assert_eq!(meshes.len(), 3);
+        assert_eq!(meshes[quad].vao, 2);
+
+        assert_eq!(meshes.remove(triangle), Some(Mesh { vao: 1, vertex_count: 3 }));
+        assert_eq!(meshes.remove(triangle), None);
+        assert!(!meshes.contains(triangle));
+        // The last value moved in the hole, its handle still works
+        assert_eq!(meshes.get(cube).map(|m| m.vao), Some(3));
+        meshes[cube].vertex_count = 24;
+        assert_eq!(meshes.values().iter().map(|m| m.vertex_count).sum::<i32>(), 30);
+
+        // The slot is reused with a new generation
+        let sphere = meshes.insert(Mesh { vao: 4, vertex_count: 960 });
+        assert_eq!(sphere.index(), triangle.index());
+        assert_ne!(sphere, triangle);
+        assert!(meshes.get(triangle).is_none());
+        assert_eq!(meshes[sphere].vao, 4);
+
+        for (handle, mesh) in &mut meshes {
+            mesh.vao += 10;
+            assert!(handle == quad || handle == cube || handle == sphere);
+        }
+        let vaos: CVec<u32, SystemAllocator> = meshes.iter().fold(CVec::new(), |mut v, (_, m)| {
+            v.push(m.vao);
+            v
+        });
+        assert_eq!(vaos.as_slice(), &[13, 12, 14]);
+    }
+
+    #[test]
+    fn test_slot_map_clear() {
+        let drops = Cell::new(0);
+        let mut map: SlotMap<DropCounter, SystemAllocator> = SlotMap::new();
+        let handles: CVec<Handle<DropCounter>, SystemAllocator> = (0..5).fold(CVec::new(), |mut v, _| {
+            v.push(map.insert(DropCounter(&drops)));
+            v
+        });
+        map.remove(handles[1]);
+        assert_eq!(drops.get(), 1);
+        map.clear();
+        assert_eq!(drops.get(), 5);
+        assert!(map.is_empty());
+        assert!(handles.iter().all(|&h| !map.contains(h)));
+        let reused = map.insert(DropCounter(&drops));
+        assert!(handles.iter().all(|&h| h != reused));
+    }
+
+    #[test]
+    #[should_panic(expected = "Stale handle Handle(0v0)")]
+    fn test_slot_map_stale_index() {
+        let mut meshes = Meshes::new();
+        let triangle = meshes.insert(Mesh { vao: 1, vertex_count: 3 });
+        meshes.remove(triangle);
+        let _ = meshes[triangle].vao;
+    }
+}
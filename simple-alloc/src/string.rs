@@ -1,69 +1,503 @@
 use crate::vec::CVec;
-use crate::alloc::Win32HeapAllocator;
+use crate::alloc::Allocator;
+use crate::system::SystemAllocator;
+use core::borrow::Borrow;
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use core::cmp::Ordering;
+use core::fmt::{self, Write};
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+use core::str;
 
-pub struct CString {
-    inner: CVec<u8, Win32HeapAllocator>
+/// Borrowed NUL-terminated string, the `&str` of `CString`.
+/// The bytes it wraps include the terminating NUL.
+#[repr(transparent)]
+pub struct CStr {
+    bytes: [u8],
 }
 
-impl CString {
+impl CStr {
+    /// `bytes` must end with the only NUL it contains.
+    pub fn from_bytes_with_nul(bytes: &[u8]) -> Option<&CStr> {
+        match bytes.iter().position(|&b| b == 0) {
+            Some(end) if end == bytes.len() - 1 => Some(unsafe { Self::from_bytes_with_nul_unchecked(bytes) }),
+            _ => None,
+        }
+    }
+
+    /// Takes the string up to the first NUL, for buffers filled by C code.
+    pub fn from_bytes_until_nul(bytes: &[u8]) -> Option<&CStr> {
+        let end = bytes.iter().position(|&b| b == 0)?;
+        Some(unsafe { Self::from_bytes_with_nul_unchecked(&bytes[..=end]) })
+    }
+
+    /// # Safety
+    /// `bytes` must end with a NUL and contain no other one.
+    pub const unsafe fn from_bytes_with_nul_unchecked(bytes: &[u8]) -> &CStr {
+        &*(bytes as *const [u8] as *const CStr)
+    }
+
+    /// # Safety
+    /// `ptr` must point to a NUL-terminated string that outlives `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const i8) -> &'a CStr {
+        let mut len = 0;
+        while ptr.add(len).read() != 0 {
+            len += 1;
+        }
+        Self::from_bytes_with_nul_unchecked(core::slice::from_raw_parts(ptr as *const u8, len + 1))
+    }
+
+    pub fn as_ptr(&self) -> *const i8 {
+        self.bytes.as_ptr() as *const i8
+    }
+
+    /// Length without the terminating NUL.
+    pub fn len(&self) -> usize {
+        self.bytes.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len()]
+    }
+
+    pub fn to_bytes_with_nul(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn to_str(&self) -> Option<&str> {
+        str::from_utf8(self.to_bytes()).ok()
+    }
+}
+
+impl PartialEq for CStr {
+    fn eq(&self, other: &CStr) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for CStr {}
+
+impl fmt::Debug for CStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for chunk in self.to_bytes().utf8_chunks() {
+            write!(f, "{}", chunk.valid().escape_debug())?;
+            for b in chunk.invalid() {
+                write!(f, "\\x{:02x}", b)?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// Whether `bytes` ends with the only NUL it contains, usable in const context.
+pub const fn is_nul_terminated(bytes: &[u8]) -> bool {
+    if bytes.is_empty() || bytes[bytes.len() - 1] != 0 {
+        return false;
+    }
+    let mut i = 0;
+    while i < bytes.len() - 1 {
+        if bytes[i] == 0 {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// `&'static CStr` from a string literal, without allocating:
+///
+/// ```ignore
+/// let func = get_gl_func_address(cstr!("glCreateShader"));
+/// ```
+///
+/// A NUL inside the literal is a compile error:
+///
+/// ```compile_fail
+/// let _ = simplealloc::cstr!("gl\0Clear");
+/// ```
+#[macro_export]
+macro_rules! cstr {
+    ($s:expr) => {{
+        const BYTES: &[u8] = concat!($s, "\0").as_bytes();
+        const _: () = assert!($crate::string::is_nul_terminated(BYTES), "cstr! literal contains a NUL");
+        unsafe { $crate::string::CStr::from_bytes_with_nul_unchecked(BYTES) }
+    }};
+}
+
+/// Owned UTF-8 string, always NUL-terminated so it can be handed to C
+/// with `as_c_ptr` without copying. C code sees it up to its first NUL.
+pub struct CString<A: Allocator = SystemAllocator> {
+    // Empty, or the content followed by a NUL
+    inner: CVec<u8, A>
+}
+
+impl<A: Allocator + Default> CString<A> {
     pub fn new() -> Self {
+        Self::new_in(A::default())
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(src: &str) -> Self {
+        Self::from_str_in(src, A::default())
+    }
+
+    pub fn from_u8_slice(src: &[u8]) -> Self {
+        Self::from_u8_slice_in(src, A::default())
+    }
+
+    pub fn from_utf16(src: &[u16]) -> Option<Self> {
+        Self::from_utf16_in(src, A::default())
+    }
+
+    pub fn from_utf16_lossy(src: &[u16]) -> Self {
+        Self::from_utf16_lossy_in(src, A::default())
+    }
+
+    /// Backs the `cformat!` macro.
+    pub fn from_fmt(args: fmt::Arguments) -> Self {
+        Self::from_fmt_in(args, A::default())
+    }
+}
+
+impl<A: Allocator + Default> Default for CString<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Allocator> CString<A> {
+    pub fn new_in(alloc: A) -> Self {
         Self {
-            inner: CVec::new()
+            inner: CVec::new_in(alloc)
         }
     }
 
-    pub fn from_str(src: &str) -> Self {
-        let mut res = CString::new();
-        for &c in src.as_bytes() {
-            res.inner.push(c);
+    pub fn from_str_in(src: &str, alloc: A) -> Self {
+        let mut res = CString::new_in(alloc);
+        res.push_str(src);
+        res
+    }
+
+    /// Takes `src` up to its first NUL, like the buffers GL and Win32 fill,
+    /// invalid UTF-8 sequences become U+FFFD.
+    pub fn from_u8_slice_in(src: &[u8], alloc: A) -> Self {
+        let src = match src.iter().position(|&b| b == 0) {
+            Some(end) => &src[..end],
+            None => src,
+        };
+
+        let mut res = CString::new_in(alloc);
+        for chunk in src.utf8_chunks() {
+            res.push_str(chunk.valid());
+            if !chunk.invalid().is_empty() {
+                res.push(REPLACEMENT_CHARACTER);
+            }
         }
-        res.inner.push(b'\0');
         res
     }
 
-    pub fn from_u8_slice(src: &[u8]) -> Self {
-        let mut res = CString::new();
-        for &c in src {
-            res.inner.push(c);
+    /// Decodes a UTF-16 string, stopping at the first NUL as Win32 buffers
+    /// are usually larger than their content. Returns `None` on unpaired surrogates.
+    pub fn from_utf16_in(src: &[u16], alloc: A) -> Option<Self> {
+        let mut res = CString::new_in(alloc);
+        for c in decode_utf16(until_nul(src).iter().copied()) {
+            res.push(c.ok()?);
+        }
+        Some(res)
+    }
+
+    /// Same as `from_utf16_in` but replaces unpaired surrogates with U+FFFD.
+    pub fn from_utf16_lossy_in(src: &[u16], alloc: A) -> Self {
+        let mut res = CString::new_in(alloc);
+        for c in decode_utf16(until_nul(src).iter().copied()) {
+            res.push(c.unwrap_or(REPLACEMENT_CHARACTER));
         }
-        res.inner.push(b'\0');
         res
     }
 
+    pub fn from_fmt_in(args: fmt::Arguments, alloc: A) -> Self {
+        let mut res = CString::new_in(alloc);
+        res.write_fmt(args).expect("A formatting trait implementation returned an error");
+        res
+    }
+
+    pub fn push(&mut self, c: char) {
+        let mut buf = [0; 4];
+        self.push_str(c.encode_utf8(&mut buf));
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
+        // Overwrite the terminating NUL and put it back at the end
+        self.inner.pop();
+        self.inner.reserve(s.len() + 1);
+        self.inner.extend_from_slice(s.as_bytes());
+        self.inner.push(b'\0');
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Length in bytes, without the terminating NUL.
     pub fn len(&self) -> usize {
-        self.inner.len()
+        self.inner.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
     }
 
     pub fn as_ptr(&self) -> *const u8 {
-        self.inner.as_ptr()
+        self.as_c_str().as_ptr() as *const u8
     }
 
-    pub fn to_i8_str(&self) -> CVec<i8, Win32HeapAllocator> {
-        let mut i8_str: CVec<i8, Win32HeapAllocator> = CVec::new();
-        for c in &self.inner {
-            i8_str.push(c as i8);
-        }
+    /// Pointer to the NUL-terminated string, for C functions taking a `const char*`.
+    pub fn as_c_ptr(&self) -> *const i8 {
+        self.as_c_str().as_ptr()
+    }
 
-        i8_str
+    /// The string up to its first NUL, pushed `'\0'` included.
+    pub fn as_c_str(&self) -> &CStr {
+        let bytes: &[u8] = if self.inner.is_empty() { b"\0" } else { &self.inner };
+        CStr::from_bytes_until_nul(bytes).expect("CString lost its NUL terminator")
     }
 
-    pub fn to_u16_str(&self) -> CVec<u16, Win32HeapAllocator> {
-        let mut u16_str: CVec<u16, Win32HeapAllocator> = CVec::new();
-        for c in &self.inner {
-            u16_str.push(c as u16);
-        }
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.inner[..self.len()]) }
+    }
+
+}
 
+impl<A: Allocator + Clone> CString<A> {
+    /// Encodes the string as NUL-terminated UTF-16 for the `W` Win32 functions,
+    /// in the string's allocator.
+    pub fn to_u16_str(&self) -> CVec<u16, A> {
+        let mut u16_str = CVec::with_capacity_in(self.inner.len() + 1, self.inner.allocator().clone());
+        u16_str.extend(self.encode_utf16());
+        u16_str.push(0);
         u16_str
     }
 }
 
+impl<A: Allocator> Deref for CString<A> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<A: Allocator> Borrow<str> for CString<A> {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<A: Allocator + Clone> Clone for CString<A> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+impl<A: Allocator> Write for CString<A> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl<A: Allocator, B: Allocator> PartialEq<CString<B>> for CString<A> {
+    fn eq(&self, other: &CString<B>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<A: Allocator> Eq for CString<A> {}
+
+impl<A: Allocator> PartialEq<str> for CString<A> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, A: Allocator> PartialEq<&'a str> for CString<A> {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<A: Allocator> PartialOrd for CString<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: Allocator> Ord for CString<A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+// Same hash as `str`, so maps keyed by `CString` can be queried with a `&str`
+impl<A: Allocator> Hash for CString<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl<A: Allocator> fmt::Display for CString<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<A: Allocator> fmt::Debug for CString<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Same as `format!`, but builds a `CString`:
+///
+/// ```ignore
+/// let title = cformat!("Shader {} failed: {:?}", id, status);
+/// ```
+#[macro_export]
+macro_rules! cformat {
+    ($($arg:tt)*) => {
+        $crate::CString::<$crate::SystemAllocator>::from_fmt(format_args!($($arg)*))
+    };
+}
+
+fn until_nul(src: &[u16]) -> &[u16] {
+    match src.iter().position(|&c| c == 0) {
+        Some(end) => &src[..end],
+        None => src,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hashmap::CHashMap;
+    use crate::tracking::TrackingAllocator;
+
+    type Str = CString<SystemAllocator>;
 
     #[test]
     fn test_wstring() {
-        let func_name = CString::from_str("glClearColor");
-        // func_name.as_ptr();
+        let func_name = Str::from_str("glClearColor");
+        assert_eq!(func_name.len(), 12);
+        assert_eq!(unsafe { func_name.as_ptr().add(12).read() }, 0);
+    }
+
+    #[test]
+    fn test_utf16() {
+        let title = Str::from_str("Démo ✓ 🎵");
+        let wide = title.to_u16_str();
+        assert_eq!(wide.as_slice(), &[0x44, 0xE9, 0x6D, 0x6F, 0x20, 0x2713, 0x20, 0xD83C, 0xDFB5, 0]);
+
+        let back = Str::from_utf16(&wide).unwrap();
+        assert_eq!(back, title);
+
+        // Win32 fills fixed size buffers
+        let buffer = [0x4E, 0x69, 0, 0x41, 0x41];
+        assert_eq!(Str::from_utf16(&buffer).unwrap(), "Ni");
+    }
+
+    #[test]
+    fn test_utf16_invalid() {
+        assert!(Str::from_utf16(&[0x41, 0xD800, 0x41]).is_none());
+        let lossy = Str::from_utf16_lossy(&[0x41, 0xD800, 0x41]);
+        assert_eq!(lossy, "A\u{FFFD}A");
+
+        let bytes = Str::from_u8_slice(&[b'a', 0xFF, b'b']);
+        assert_eq!(bytes.to_u16_str().as_slice(), &[0x61, 0xFFFD, 0x62, 0]);
+    }
+
+    #[test]
+    fn test_format() {
+        let mut text = cformat!("frame {} took {:.2}ms", 42, 16.666);
+        assert_eq!(text, "frame 42 took 16.67ms");
+        assert_eq!(unsafe { text.as_ptr().add(text.len()).read() }, 0);
+        write!(text, ", error {:#06x}", 0x502).unwrap();
+        assert_eq!(text, "frame 42 took 16.67ms, error 0x0502");
+
+        let name = Str::from_str("Démo");
+        assert_eq!(cformat!("{} {:?}", name, name), "Démo \"Démo\"");
+        assert_eq!(Str::new().as_bytes(), b"");
+    }
+
+    #[test]
+    fn test_c_str() {
+        let mut name = Str::new();
+        assert_eq!(name.as_c_str().to_bytes_with_nul(), b"\0");
+        name.push_str("glGen");
+        name.push('B');
+        name.push_str("uffers");
+        assert_eq!(name, "glGenBuffers");
+        assert_eq!(name.len(), 12);
+        assert!(name.starts_with("glGen"));
+
+        let c_str = unsafe { CStr::from_ptr(name.as_c_ptr()) };
+        assert_eq!(c_str.len(), 12);
+        assert_eq!(c_str.to_str(), Some("glGenBuffers"));
+        assert_eq!(c_str, cstr!("glGenBuffers"));
+        assert_eq!(c_str.as_ptr(), name.as_c_ptr());
+
+        const SHADER: &CStr = cstr!("void main() {}");
+        assert_eq!(SHADER.to_bytes_with_nul(), b"void main() {}\0");
+
+        assert!(CStr::from_bytes_with_nul(b"gl\0Clear\0").is_none());
+        assert!(CStr::from_bytes_with_nul(b"glClear").is_none());
+        let log = [b'o', b'k', 0, 0xAA, 0xAA];
+        assert_eq!(CStr::from_bytes_until_nul(&log).unwrap().to_bytes(), b"ok");
+        assert_eq!(Str::from_u8_slice(&log), "ok");
+
+        // C code stops at the first NUL, so does the `CStr`
+        let mut split = Str::from_str("gl");
+        split.push('\0');
+        split.push_str("Clear");
+        assert_eq!(split.len(), 8);
+        assert_eq!(split.as_c_str().to_bytes_with_nul(), b"gl\0");
+    }
+
+    #[test]
+    fn test_other_allocator() {
+        let tracker = TrackingAllocator::new(SystemAllocator::default());
+        let title = CString::from_str_in("Démo", tracker.handle());
+        // The UTF-16 copy comes from the string's allocator
+        let wide = title.to_u16_str();
+        assert_eq!(wide.as_slice(), &[0x44, 0xE9, 0x6D, 0x6F, 0]);
+        assert_eq!(tracker.stats().live_count, 2);
+
+        // Default-constructible allocators don't need the `_in` constructors
+        let name: CString<TrackingAllocator<SystemAllocator>> = CString::from_str("glClear");
+        assert_eq!(name, "glClear");
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_compare_hash() {
+        let a = Str::from_str("uTime");
+        let b = Str::from_str("uColor");
+        assert!(b < a);
+        assert_eq!(a.clone(), a);
+
+        let mut uniforms: CHashMap<CString, i32, SystemAllocator> = CHashMap::new();
+        uniforms.insert(a, 3);
+        uniforms.insert(b, 7);
+        assert_eq!(uniforms.get("uTime"), Some(&3));
+        assert_eq!(uniforms.get("uColor"), Some(&7));
+        assert_eq!(uniforms.get("uScale"), None);
+    }
+}
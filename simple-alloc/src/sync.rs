@@ -0,0 +1,442 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint::spin_loop;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Gives the rest of the time slice to another thread.
+pub fn yield_now() {
+    #[cfg(windows)]
+    unsafe {
+        win32::SwitchToThread();
+    }
+    #[cfg(target_os = "linux")]
+    crate::system::linux::sched_yield();
+    #[cfg(not(any(windows, target_os = "linux")))]
+    spin_loop();
+}
+
+/// Waiting strategy of the spin locks: spins `2^step` times for the first
+/// `spin_limit` steps, then yields to the OS so a preempted lock holder can run.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    step: u32,
+    spin_limit: u32,
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self::with_spin_limit(6)
+    }
+
+    /// A limit of 0 yields right away, which suits locks held across long work.
+    pub const fn with_spin_limit(spin_limit: u32) -> Self {
+        Self { step: 0, spin_limit }
+    }
+
+    pub fn snooze(&mut self) {
+        if self.is_yielding() {
+            yield_now();
+        } else {
+            for _ in 0..1u32 << self.step {
+                spin_loop();
+            }
+            self.step += 1;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+
+    /// True once spinning is over and `snooze` yields.
+    pub fn is_yielding(&self) -> bool {
+        self.step >= self.spin_limit
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mutual exclusion lock that spins instead of sleeping, usable in statics:
+///
+/// ```ignore
+/// static MUSIC_TIME: SpinMutex<f32> = SpinMutex::new(0.0);
+/// *MUSIC_TIME.lock() += dt;
+/// ```
+pub struct SpinMutex<T: ?Sized> {
+    locked: AtomicBool,
+    backoff: Backoff,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for SpinMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for SpinMutex<T> {}
+
+impl<T> SpinMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self::with_backoff(value, Backoff::new())
+    }
+
+    pub const fn with_backoff(value: T, backoff: Backoff) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            backoff,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinMutex<T> {
+    pub fn lock(&self) -> SpinMutexGuard<'_, T> {
+        let mut backoff = self.backoff;
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            // Wait on a plain load to keep the cache line shared
+            while self.is_locked() {
+                backoff.snooze();
+            }
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<SpinMutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinMutexGuard { mutex: self, _not_sync: PhantomData })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// No locking needed, the borrow proves nobody else holds the lock.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for SpinMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("SpinMutex").field("value", &&*guard).finish(),
+            None => f.write_str("SpinMutex { <locked> }"),
+        }
+    }
+}
+
+/// Sharing the guard shares the `T` it derefs to, so it is only `Sync` when
+/// `T` is:
+///
+/// ```compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<simplealloc::SpinMutexGuard<core::cell::Cell<u32>>>();
+/// ```
+pub struct SpinMutexGuard<'a, T: ?Sized> {
+    mutex: &'a SpinMutex<T>,
+    // Opts out of the auto impls, which would only need `SpinMutex<T>: Sync`
+    _not_sync: PhantomData<*const ()>,
+}
+
+unsafe impl<'a, T: ?Sized + Send> Send for SpinMutexGuard<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync> Sync for SpinMutexGuard<'a, T> {}
+
+impl<'a, T: ?Sized> Deref for SpinMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for SpinMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+    }
+}
+
+// `state` counts the readers, or is WRITER while a writer holds the lock
+const WRITER: usize = usize::MAX;
+
+/// Reader-writer lock that spins instead of sleeping. Waiting writers stop
+/// new readers from coming in, so a busy render loop can't starve them.
+pub struct SpinRwLock<T: ?Sized> {
+    state: AtomicUsize,
+    waiting_writers: AtomicUsize,
+    backoff: Backoff,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for SpinRwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for SpinRwLock<T> {}
+
+impl<T> SpinRwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self::with_backoff(value, Backoff::new())
+    }
+
+    pub const fn with_backoff(value: T, backoff: Backoff) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            waiting_writers: AtomicUsize::new(0),
+            backoff,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinRwLock<T> {
+    pub fn read(&self) -> SpinReadGuard<'_, T> {
+        let mut backoff = self.backoff;
+        loop {
+            // Spurious failures of the weak exchange just go round the loop
+            if let Some(readers) = self.readers_if_open() {
+                let acquired = self.state.compare_exchange_weak(readers, readers + 1, Ordering::Acquire, Ordering::Relaxed);
+                if acquired.is_ok() {
+                    return SpinReadGuard { lock: self };
+                }
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Fails while a writer holds or waits for the lock.
+    pub fn try_read(&self) -> Option<SpinReadGuard<'_, T>> {
+        let readers = self.readers_if_open()?;
+        self.state
+            .compare_exchange(readers, readers + 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinReadGuard { lock: self })
+    }
+
+    // Current reader count, `None` if a new reader can't come in
+    fn readers_if_open(&self) -> Option<usize> {
+        if self.waiting_writers.load(Ordering::Relaxed) > 0 {
+            return None;
+        }
+
+        let readers = self.state.load(Ordering::Relaxed);
+        if readers >= WRITER - 1 { None } else { Some(readers) }
+    }
+
+    pub fn write(&self) -> SpinWriteGuard<'_, T> {
+        self.waiting_writers.fetch_add(1, Ordering::Relaxed);
+        let mut backoff = self.backoff;
+        let guard = loop {
+            if let Some(guard) = self.try_write() {
+                break guard;
+            }
+            backoff.snooze();
+        };
+        self.waiting_writers.fetch_sub(1, Ordering::Relaxed);
+        guard
+    }
+
+    pub fn try_write(&self) -> Option<SpinWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinWriteGuard { lock: self })
+    }
+
+    /// Number of readers holding the lock, 0 while a writer has it.
+    pub fn reader_count(&self) -> usize {
+        match self.state.load(Ordering::Relaxed) {
+            WRITER => 0,
+            readers => readers,
+        }
+    }
+
+    pub fn is_write_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == WRITER
+    }
+
+    /// No locking needed, the borrow proves nobody else holds the lock.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for SpinRwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinRwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_struct("SpinRwLock").field("value", &&*guard).finish(),
+            None => f.write_str("SpinRwLock { <locked> }"),
+        }
+    }
+}
+
+pub struct SpinReadGuard<'a, T: ?Sized> {
+    lock: &'a SpinRwLock<T>,
+}
+
+impl<'a, T: ?Sized> Deref for SpinReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+pub struct SpinWriteGuard<'a, T: ?Sized> {
+    lock: &'a SpinRwLock<T>,
+}
+
+impl<'a, T: ?Sized> Deref for SpinWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for SpinWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::with_spin_limit(3);
+        for _ in 0..3 {
+            assert!(!backoff.is_yielding());
+            backoff.snooze();
+        }
+        assert!(backoff.is_yielding());
+        backoff.snooze();
+        backoff.reset();
+        assert!(!backoff.is_yielding());
+        assert!(Backoff::with_spin_limit(0).is_yielding());
+    }
+
+    #[test]
+    fn test_spin_mutex() {
+        static FRAME: SpinMutex<u64> = SpinMutex::new(0);
+        {
+            let mut frame = FRAME.lock();
+            *frame += 1;
+            assert!(FRAME.is_locked());
+            assert!(FRAME.try_lock().is_none());
+        }
+        assert_eq!(*FRAME.try_lock().unwrap(), 1);
+
+        let samples = Arc::new(SpinMutex::with_backoff(Vec::new(), Backoff::with_spin_limit(0)));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let samples = samples.clone();
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        samples.lock().push(t * 1000 + i);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let mut samples = Arc::try_unwrap(samples).unwrap().into_inner();
+        assert_eq!(samples.len(), 2000);
+        samples.sort_unstable();
+        samples.dedup();
+        assert_eq!(samples.len(), 2000);
+    }
+
+    #[test]
+    fn test_spin_rw_lock() {
+        let lock = SpinRwLock::new([0u32; 4]);
+        {
+            let a = lock.read();
+            let b = lock.try_read().unwrap();
+            assert_eq!(lock.reader_count(), 2);
+            assert!(lock.try_write().is_none());
+            assert_eq!(a[0], b[0]);
+        }
+        {
+            let mut w = lock.write();
+            w[0] = 7;
+            assert!(lock.is_write_locked());
+            assert!(lock.try_read().is_none());
+        }
+        assert_eq!(lock.read()[0], 7);
+
+        // Writers keep every element equal, readers must never see a torn update
+        let lock = Arc::new(SpinRwLock::new([0u32; 4]));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    for _ in 0..500 {
+                        if t % 2 == 0 {
+                            let mut values = lock.write();
+                            for v in values.iter_mut() {
+                                *v += 1;
+                            }
+                        } else {
+                            let values = lock.read();
+                            assert!(values.iter().all(|&v| v == values[0]));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(*lock.read(), [1000; 4]);
+    }
+}
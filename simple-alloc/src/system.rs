@@ -0,0 +1,199 @@
+use crate::alloc::*;
+use core::ffi::c_void;
+
+#[cfg(not(any(windows, target_os = "linux")))]
+compile_error!("simple-alloc: unsupported target, only Windows and Linux have a system allocator");
+
+#[cfg(all(target_os = "linux", not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+compile_error!("simple-alloc: unsupported target, the Linux backend needs x86_64 or aarch64");
+
+/// Default allocator of the platform: the process heap on Windows,
+/// anonymous mappings on Linux.
+#[derive(Clone, Default)]
+pub struct SystemAllocator {
+    #[cfg(windows)]
+    heap: Win32HeapAllocator,
+}
+
+#[cfg(windows)]
+impl Allocator for SystemAllocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        self.heap.alloc(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout) {
+        self.heap.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void> {
+        self.heap.realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Allocator for SystemAllocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        if layout.align > linux::PAGE_SIZE {
+            let ptr = linux::mmap(over_aligned_size(layout))?;
+            Some(align_block(ptr, layout.align))
+        } else {
+            linux::mmap(layout.size)
+        }
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout) {
+        if layout.align > linux::PAGE_SIZE {
+            linux::munmap(unalign_block(ptr), over_aligned_size(layout));
+        } else {
+            linux::munmap(ptr, layout.size);
+        }
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void> {
+        // mremap keeps the page offset, which is all the alignment we get
+        if layout.align > linux::PAGE_SIZE {
+            return realloc_by_copy(self, ptr, layout, new_size);
+        }
+        linux::mremap(ptr, layout.size, new_size)
+    }
+}
+
+// Raw syscalls, we don't link against libc
+#[cfg(target_os = "linux")]
+pub(crate) mod linux {
+    use core::arch::asm;
+    use core::ffi::c_void;
+
+    // Smallest page size of the supported kernels, so the alignment mmap always
+    // gives. aarch64 kernels can use bigger pages, see `pages::PAGE_SIZE`.
+    pub const PAGE_SIZE: usize = 4096;
+
+    pub const PROT_NONE: usize = 0x0;
+    pub const PROT_READ: usize = 0x1;
+    pub const PROT_WRITE: usize = 0x2;
+    pub const PROT_EXEC: usize = 0x4;
+    const MAP_PRIVATE: usize = 0x02;
+    const MAP_ANONYMOUS: usize = 0x20;
+    const MAP_NORESERVE: usize = 0x4000;
+    const MREMAP_MAYMOVE: usize = 0x1;
+
+    #[cfg(target_arch = "x86_64")]
+    mod nr {
+        pub const MMAP: usize = 9;
+        pub const MUNMAP: usize = 11;
+        pub const MREMAP: usize = 25;
+        pub const MPROTECT: usize = 10;
+        pub const SCHED_YIELD: usize = 24;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    mod nr {
+        pub const MMAP: usize = 222;
+        pub const MUNMAP: usize = 215;
+        pub const MREMAP: usize = 216;
+        pub const MPROTECT: usize = 226;
+        pub const SCHED_YIELD: usize = 124;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe fn syscall6(nr: usize, a0: usize, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> isize {
+        let ret: isize;
+        asm!(
+            "syscall",
+            inlateout("rax") nr as isize => ret,
+            in("rdi") a0,
+            in("rsi") a1,
+            in("rdx") a2,
+            in("r10") a3,
+            in("r8") a4,
+            in("r9") a5,
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack),
+        );
+        ret
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn syscall6(nr: usize, a0: usize, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> isize {
+        let ret: isize;
+        asm!(
+            "svc 0",
+            in("x8") nr,
+            inlateout("x0") a0 as isize => ret,
+            in("x1") a1,
+            in("x2") a2,
+            in("x3") a3,
+            in("x4") a4,
+            in("x5") a5,
+            options(nostack),
+        );
+        ret
+    }
+
+    // The kernel returns -errno on failure
+    fn to_ptr(ret: isize) -> Option<*mut c_void> {
+        if (-4095..0).contains(&ret) {
+            None
+        } else {
+            Some(ret as *mut c_void)
+        }
+    }
+
+    // Zero sized mappings are rejected by the kernel
+    fn map_len(size: usize) -> usize {
+        if size == 0 { 1 } else { size }
+    }
+
+    pub unsafe fn mmap(size: usize) -> Option<*mut c_void> {
+        let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+        to_ptr(syscall6(nr::MMAP, 0, map_len(size), PROT_READ | PROT_WRITE, flags, usize::MAX, 0))
+    }
+
+    /// Address range without any access, made usable with `mprotect`.
+    pub unsafe fn mmap_reserve(size: usize) -> Option<*mut c_void> {
+        let flags = MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE;
+        to_ptr(syscall6(nr::MMAP, 0, map_len(size), PROT_NONE, flags, usize::MAX, 0))
+    }
+
+    pub unsafe fn mprotect(ptr: *mut c_void, size: usize, prot: usize) -> bool {
+        syscall6(nr::MPROTECT, ptr as usize, size, prot, 0, 0, 0) == 0
+    }
+
+    pub unsafe fn munmap(ptr: *mut c_void, size: usize) {
+        syscall6(nr::MUNMAP, ptr as usize, map_len(size), 0, 0, 0, 0);
+    }
+
+    pub unsafe fn mremap(ptr: *mut c_void, old_size: usize, new_size: usize) -> Option<*mut c_void> {
+        to_ptr(syscall6(nr::MREMAP, ptr as usize, map_len(old_size), map_len(new_size), MREMAP_MAYMOVE, 0, 0))
+    }
+
+    pub fn sched_yield() {
+        unsafe { syscall6(nr::SCHED_YIELD, 0, 0, 0, 0, 0, 0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_allocator() {
+        let mut alloc = SystemAllocator::default();
+        unsafe {
+            let layout = Layout::from_array_type::<u64>(1000);
+            let ptr = alloc.alloc(layout).unwrap() as *mut u64;
+            for i in 0..1000 {
+                ptr.add(i).write(i as u64);
+            }
+            let ptr = alloc.realloc(ptr as *mut c_void, layout, layout.size * 100).unwrap() as *mut u64;
+            for i in 0..1000 {
+                assert_eq!(ptr.add(i).read(), i as u64);
+            }
+            alloc.dealloc(ptr as *mut c_void, Layout::from_array_type::<u64>(100_000));
+
+            let empty = alloc.alloc(Layout::new(0, 1)).unwrap();
+            alloc.dealloc(empty, Layout::new(0, 1));
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use crate::alloc::*;
+use core::cell::{Cell, UnsafeCell};
+use core::cmp::max;
+use core::ffi::c_void;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+
+/// Number of power of two size classes, the last one also counts every larger block.
+pub const SIZE_CLASSES: usize = 16;
+
+/// Counters kept by `TrackingAllocator`, usable on their own.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct AllocStats {
+    pub live_count: usize,
+    pub live_bytes: usize,
+    pub peak_bytes: usize,
+    pub allocs: usize,
+    pub deallocs: usize,
+    pub reallocs: usize,
+    /// Bytes requested over the whole run, including growth from reallocs.
+    pub total_bytes: usize,
+    /// Allocations per size class, class `i` holds sizes in `(2^(i-1), 2^i]`.
+    pub size_classes: [usize; SIZE_CLASSES],
+}
+
+impl AllocStats {
+    pub fn size_class(size: usize) -> usize {
+        let class = if size <= 1 {
+            0
+        } else {
+            (usize::BITS - (size - 1).leading_zeros()) as usize
+        };
+        class.min(SIZE_CLASSES - 1)
+    }
+
+    pub fn record_alloc(&mut self, size: usize) {
+        self.allocs += 1;
+        self.live_count += 1;
+        self.total_bytes += size;
+        self.size_classes[Self::size_class(size)] += 1;
+        self.add_live_bytes(size);
+    }
+
+    pub fn record_dealloc(&mut self, size: usize) {
+        self.deallocs += 1;
+        self.live_count -= 1;
+        self.live_bytes -= size;
+    }
+
+    pub fn record_realloc(&mut self, old_size: usize, new_size: usize) {
+        self.reallocs += 1;
+        if new_size > old_size {
+            self.total_bytes += new_size - old_size;
+            self.add_live_bytes(new_size - old_size);
+        } else {
+            self.live_bytes -= old_size - new_size;
+        }
+    }
+
+    fn add_live_bytes(&mut self, size: usize) {
+        self.live_bytes += size;
+        self.peak_bytes = max(self.peak_bytes, self.live_bytes);
+    }
+}
+
+impl fmt::Display for AllocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "live: {} blocks, {} bytes (peak {} bytes)", self.live_count, self.live_bytes, self.peak_bytes)?;
+        writeln!(f, "calls: {} allocs, {} deallocs, {} reallocs, {} bytes requested", self.allocs, self.deallocs, self.reallocs, self.total_bytes)?;
+        for (class, &count) in self.size_classes.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            if class == SIZE_CLASSES - 1 {
+                writeln!(f, "  > {:>6} bytes: {}", 1usize << (class - 1), count)?;
+            } else {
+                writeln!(f, "  <= {:>5} bytes: {}", 1usize << class, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Stored right before every block handed out, links the live blocks together
+#[repr(C)]
+struct Header {
+    prev: *mut Header,
+    next: *mut Header,
+    size: usize,
+    id: usize,
+}
+
+/// Outstanding block, see `TrackingAllocator::live`.
+#[derive(Clone, Copy, Debug)]
+pub struct LiveAllocation {
+    /// Allocation number, in call order starting at 0.
+    pub id: usize,
+    pub ptr: *mut c_void,
+    pub size: usize,
+}
+
+/// Wraps an allocator to count allocations and list the ones still alive:
+///
+/// ```ignore
+/// let tracker = TrackingAllocator::new(SystemAllocator::default());
+/// let name = CString::from_str_in("glClear", tracker.handle());
+/// tracker.report(&mut console)?;
+/// ```
+pub struct TrackingAllocator<A: Allocator> {
+    alloc: UnsafeCell<A>,
+    stats: Cell<AllocStats>,
+    head: Cell<*mut Header>,
+    next_id: Cell<usize>,
+}
+
+// The list only points into blocks owned by the wrapped allocator
+unsafe impl<A: Allocator + Send> Send for TrackingAllocator<A> {}
+
+impl<A: Allocator + Default> Default for TrackingAllocator<A> {
+    fn default() -> Self {
+        Self::new(A::default())
+    }
+}
+
+impl<A: Allocator> TrackingAllocator<A> {
+    pub fn new(alloc: A) -> Self {
+        Self {
+            alloc: UnsafeCell::new(alloc),
+            stats: Cell::new(AllocStats::default()),
+            head: Cell::new(ptr::null_mut()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    /// Allocator to give to containers while keeping access to the stats.
+    pub fn handle(&self) -> TrackingHandle<'_, A> {
+        TrackingHandle { tracker: self }
+    }
+
+    pub fn stats(&self) -> AllocStats {
+        self.stats.get()
+    }
+
+    /// Outstanding allocations, most recent first. Nothing must be allocated
+    /// or freed through this tracker while iterating.
+    pub fn live(&self) -> LiveIter<'_> {
+        LiveIter {
+            header: self.head.get(),
+            _tracker: PhantomData,
+        }
+    }
+
+    /// Writes the stats followed by every allocation still alive,
+    /// `out` must not allocate from this tracker.
+    pub fn report(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+        write!(out, "{}", self.stats())?;
+        for block in self.live() {
+            writeln!(out, "  leak #{}: {} bytes at {:p}", block.id, block.size, block.ptr)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> A {
+        self.alloc.into_inner()
+    }
+
+    // Room for the header, keeping the block aligned
+    fn header_offset(align: usize) -> usize {
+        let align = max(align, mem::align_of::<Header>());
+        (mem::size_of::<Header>() + align - 1) & !(align - 1)
+    }
+
+    fn outer_layout(layout: Layout) -> Layout {
+        Layout::new(Self::header_offset(layout.align) + layout.size, max(layout.align, mem::align_of::<Header>()))
+    }
+
+    unsafe fn header(ptr: *mut c_void) -> *mut Header {
+        (ptr as *mut Header).sub(1)
+    }
+
+    unsafe fn link(&self, header: *mut Header) {
+        (*header).prev = ptr::null_mut();
+        (*header).next = self.head.get();
+        if !self.head.get().is_null() {
+            (*self.head.get()).prev = header;
+        }
+        self.head.set(header);
+    }
+
+    unsafe fn unlink(&self, header: *mut Header) {
+        if (*header).prev.is_null() {
+            self.head.set((*header).next);
+        } else {
+            (*(*header).prev).next = (*header).next;
+        }
+        if !(*header).next.is_null() {
+            (*(*header).next).prev = (*header).prev;
+        }
+    }
+
+    unsafe fn tracked_alloc(&self, layout: Layout) -> Option<*mut c_void> {
+        let offset = Self::header_offset(layout.align);
+        let base = (*self.alloc.get()).alloc(Self::outer_layout(layout))? as *mut u8;
+        let ptr = base.add(offset) as *mut c_void;
+        let header = Self::header(ptr);
+        (*header).size = layout.size;
+        (*header).id = self.next_id.get();
+        self.next_id.set(self.next_id.get() + 1);
+        self.link(header);
+        self.update_stats(|stats| stats.record_alloc(layout.size));
+        Some(ptr)
+    }
+
+    unsafe fn tracked_dealloc(&self, ptr: *mut c_void, layout: Layout) {
+        self.unlink(Self::header(ptr));
+        let base = (ptr as *mut u8).sub(Self::header_offset(layout.align));
+        (*self.alloc.get()).dealloc(base as *mut c_void, Self::outer_layout(layout));
+        self.update_stats(|stats| stats.record_dealloc(layout.size));
+    }
+
+    unsafe fn tracked_realloc(&self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void> {
+        let offset = Self::header_offset(layout.align);
+        // The block may move, so its neighbours can't point to it meanwhile
+        self.unlink(Self::header(ptr));
+        let base = (ptr as *mut u8).sub(offset) as *mut c_void;
+        let new_base = (*self.alloc.get()).realloc(base, Self::outer_layout(layout), offset + new_size);
+        let new_ptr = match new_base {
+            Some(new_base) => (new_base as *mut u8).add(offset) as *mut c_void,
+            None => {
+                self.link(Self::header(ptr));
+                return None;
+            }
+        };
+
+        let header = Self::header(new_ptr);
+        (*header).size = new_size;
+        self.link(header);
+        self.update_stats(|stats| stats.record_realloc(layout.size, new_size));
+        Some(new_ptr)
+    }
+
+    fn update_stats<F: FnOnce(&mut AllocStats)>(&self, f: F) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+impl<A: Allocator> Allocator for TrackingAllocator<A> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        self.tracked_alloc(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout) {
+        self.tracked_dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void> {
+        self.tracked_realloc(ptr, layout, new_size)
+    }
+}
+
+pub struct LiveIter<'a> {
+    header: *mut Header,
+    _tracker: PhantomData<&'a Header>,
+}
+
+impl<'a> Iterator for LiveIter<'a> {
+    type Item = LiveAllocation;
+
+    fn next(&mut self) -> Option<LiveAllocation> {
+        if self.header.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let header = &*self.header;
+            self.header = header.next;
+            Some(LiveAllocation {
+                id: header.id,
+                ptr: (header as *const Header).add(1) as *mut c_void,
+                size: header.size,
+            })
+        }
+    }
+}
+
+pub struct TrackingHandle<'a, A: Allocator> {
+    tracker: &'a TrackingAllocator<A>,
+}
+
+impl<'a, A: Allocator> Clone for TrackingHandle<'a, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, A: Allocator> Copy for TrackingHandle<'a, A> {}
+
+impl<'a, A: Allocator> Allocator for TrackingHandle<'a, A> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<*mut c_void> {
+        self.tracker.tracked_alloc(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut c_void, layout: Layout) {
+        self.tracker.tracked_dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut c_void, layout: Layout, new_size: usize) -> Option<*mut c_void> {
+        self.tracker.tracked_realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::string::CString;
+    use crate::system::SystemAllocator;
+    use crate::vec::CVec;
+
+    type Tracker = TrackingAllocator<SystemAllocator>;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(AllocStats::size_class(0), 0);
+        assert_eq!(AllocStats::size_class(1), 0);
+        assert_eq!(AllocStats::size_class(2), 1);
+        assert_eq!(AllocStats::size_class(3), 2);
+        assert_eq!(AllocStats::size_class(4096), 12);
+        assert_eq!(AllocStats::size_class(4097), 13);
+        assert_eq!(AllocStats::size_class(usize::MAX), SIZE_CLASSES - 1);
+
+        let mut stats = AllocStats::default();
+        stats.record_alloc(100);
+        stats.record_alloc(20);
+        stats.record_realloc(100, 300);
+        stats.record_dealloc(20);
+        stats.record_realloc(300, 50);
+        assert_eq!(stats.live_count, 1);
+        assert_eq!(stats.live_bytes, 50);
+        assert_eq!(stats.peak_bytes, 320);
+        assert_eq!(stats.total_bytes, 320);
+        assert_eq!((stats.allocs, stats.deallocs, stats.reallocs), (2, 1, 2));
+        // Reallocs resize a block already counted by its allocation
+        assert_eq!(stats.size_classes[7], 1);
+        assert_eq!(stats.size_classes[5], 1);
+        assert_eq!(stats.size_classes.iter().sum::<usize>(), stats.allocs);
+    }
+
+    #[test]
+    fn test_tracking_containers() {
+        let tracker = Tracker::default();
+        {
+            let mut cvec: CVec<u64, _> = CVec::new_in(tracker.handle());
+            for i in 0..100 {
+                cvec.push(i);
+            }
+            let name = CString::from_str_in("glGetShaderiv", tracker.handle());
+            assert_eq!(cvec[99], 99);
+            assert_eq!(name.len(), 13);
+
+            let stats = tracker.stats();
+            assert_eq!(stats.live_count, 2);
+            assert_eq!(stats.live_bytes, cvec.capacity() * 8 + name.len() + 1);
+            assert_eq!(tracker.live().count(), 2);
+        }
+        let stats = tracker.stats();
+        assert_eq!(stats.live_count, 0);
+        assert_eq!(stats.live_bytes, 0);
+        assert!(stats.peak_bytes >= 800);
+        assert_eq!(tracker.live().count(), 0);
+    }
+
+    #[test]
+    fn test_tracking_leaks() {
+        let mut tracker = Tracker::default();
+        unsafe {
+            let a = tracker.alloc(Layout::new(24, 8)).unwrap();
+            let b = tracker.alloc(Layout::new(100, 256)).unwrap();
+            assert_eq!(b as usize % 256, 0);
+            let c = tracker.alloc(Layout::new(7, 1)).unwrap();
+            tracker.dealloc(a, Layout::new(24, 8));
+            let c = tracker.realloc(c, Layout::new(7, 1), 5000).unwrap();
+
+            let leaks: CVec<(usize, usize), SystemAllocator> = tracker.live().fold(CVec::new(), |mut v, block| {
+                v.push((block.id, block.size));
+                v
+            });
+            assert_eq!(leaks.as_slice(), &[(2, 5000), (1, 100)]);
+
+            let mut report: CString = CString::new();
+            tracker.report(&mut report).unwrap();
+            let report = core::str::from_utf8(report.as_bytes()).unwrap();
+            assert!(report.starts_with("live: 2 blocks, 5100 bytes"));
+            assert!(report.contains("leak #1: 100 bytes"));
+            assert!(report.contains("leak #2: 5000 bytes"));
+            assert!(report.contains("<=   128 bytes: 1"));
+
+            tracker.dealloc(b, Layout::new(100, 256));
+            tracker.dealloc(c, Layout::new(5000, 1));
+        }
+        assert_eq!(tracker.live().count(), 0);
+    }
+}
@@ -1,13 +1,16 @@
 use crate::alloc::*;
 use core::ffi::c_void;
-use core::ptr;
-use core::ops::{Index, IndexMut};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use core::ops::{Deref, DerefMut, Index, IndexMut};
+use core::slice::{self, SliceIndex};
 
 pub struct RawVec<T, A: Allocator> {
     pub ptr: *mut T,
     pub capacity: usize,
     alloc: A,
-    // _phantom: PhantomData<T>,
+    _phantom: PhantomData<T>,
 }
 
 impl<T, A: Allocator> RawVec<T, A> {
@@ -15,7 +18,8 @@ impl<T, A: Allocator> RawVec<T, A> {
         Self {
             ptr: core::ptr::null_mut(),
             capacity: 0,
-            alloc: alloc
+            alloc,
+            _phantom: PhantomData,
         }
     }
 
@@ -24,19 +28,39 @@ impl<T, A: Allocator> RawVec<T, A> {
             return;
         }
 
-        let layout = Layout::from_array_type::<T>(new_capacity);
-        let ptr = unsafe { self.alloc.alloc(layout).expect("Allcation error") as *mut T };
+        self.resize(new_capacity);
+    }
+
+    pub fn shrink_to(&mut self, new_capacity: usize) {
+        if new_capacity >= self.capacity {
+            return;
+        }
 
-        if self.capacity > 0 {
+        if new_capacity == 0 {
             unsafe {
-                ptr.copy_from(self.ptr, self.capacity);
-                self.alloc.dealloc(self.ptr as *mut c_void);
+                self.alloc.dealloc(self.ptr as *mut c_void, Layout::from_array_type::<T>(self.capacity));
             }
+            self.ptr = ptr::null_mut();
+            self.capacity = 0;
+            return;
         }
 
-        self.ptr = ptr;
+        self.resize(new_capacity);
+    }
+
+    fn resize(&mut self, new_capacity: usize) {
+        let ptr = unsafe {
+            if self.capacity == 0 {
+                self.alloc.alloc(Layout::from_array_type::<T>(new_capacity))
+            } else {
+                // Let the allocator grow or shrink the block in place when it can
+                let layout = Layout::from_array_type::<T>(self.capacity);
+                self.alloc.realloc(self.ptr as *mut c_void, layout, Layout::from_array_type::<T>(new_capacity).size)
+            }
+        };
+
+        self.ptr = ptr.expect("Allocation error") as *mut T;
         self.capacity = new_capacity;
-        
     }
 }
 
@@ -47,7 +71,7 @@ impl<T, A: Allocator> Drop for RawVec<T, A>
         if !self.ptr.is_null()
         {
             unsafe {
-                self.alloc.dealloc(self.ptr as *mut c_void);
+                self.alloc.dealloc(self.ptr as *mut c_void, Layout::from_array_type::<T>(self.capacity));
             }
         }
     }
@@ -60,12 +84,40 @@ pub struct CVec<T, A: Allocator> {
 
 impl<T, A: Allocator + Default> CVec<T, A> {
     pub fn new() -> Self {
+        Self::new_in(A::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, A::default())
+    }
+}
+
+impl<T, A: Allocator + Default> Default for CVec<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A: Allocator> CVec<T, A> {
+    pub fn new_in(alloc: A) -> Self {
         Self {
             length: 0,
-            buffer: RawVec::new(A::default())
+            buffer: RawVec::new(alloc)
         }
     }
 
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let mut res = Self::new_in(alloc);
+        if capacity > 0 {
+            res.buffer.reserve(capacity);
+        }
+        res
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.buffer.alloc
+    }
+
     fn grow(&mut self) {
         if self.length == 0 {
             self.buffer.reserve(1);
@@ -74,13 +126,21 @@ impl<T, A: Allocator + Default> CVec<T, A> {
         }
     }
 
+    /// Makes room for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.length.checked_add(additional).expect("Capacity overflow");
+        if needed > self.buffer.capacity {
+            self.buffer.reserve(core::cmp::max(needed, self.length * 2));
+        }
+    }
+
     pub fn push(&mut self, value: T) {
         if self.length == self.buffer.capacity {
             self.grow();
         }
 
         unsafe {
-            self.buffer.ptr.offset(self.length as isize).write(value);
+            self.buffer.ptr.add(self.length).write(value);
         }
 
         self.length += 1;
@@ -91,20 +151,87 @@ impl<T, A: Allocator + Default> CVec<T, A> {
             None
         } else {
             let value = unsafe {
-                self.buffer.ptr.offset((self.length - 1) as isize).read()
+                self.buffer.ptr.add(self.length - 1).read()
             };
             self.length -= 1;
             Some(value)
         }
     }
 
-    pub fn clear(&mut self) {
-        for i in 0..self.length {
-            unsafe {
-                ptr::drop_in_place(self.buffer.ptr.offset(i as isize));
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.length, "Insertion index {} out of bounds (len {})", index, self.length);
+        if self.length == self.buffer.capacity {
+            self.grow();
+        }
+
+        unsafe {
+            let p = self.buffer.ptr.add(index);
+            ptr::copy(p, p.add(1), self.length - index);
+            p.write(value);
+        }
+        self.length += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.length, "Removal index {} out of bounds (len {})", index, self.length);
+        unsafe {
+            let p = self.buffer.ptr.add(index);
+            let value = p.read();
+            ptr::copy(p.add(1), p, self.length - index - 1);
+            self.length -= 1;
+            value
+        }
+    }
+
+    /// Removes an element in O(1) by moving the last one in its place.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.length, "Removal index {} out of bounds (len {})", index, self.length);
+        unsafe {
+            let last = self.buffer.ptr.add(self.length - 1).read();
+            self.length -= 1;
+            if index == self.length {
+                last
+            } else {
+                ptr::replace(self.buffer.ptr.add(index), last)
             }
         }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.length {
+            return;
+        }
+
+        // Shorten first so a panicking destructor can't cause a double drop
+        let tail = ptr::slice_from_raw_parts_mut(unsafe { self.buffer.ptr.add(len) }, self.length - len);
+        self.length = len;
+        unsafe {
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    /// Keeps only the elements for which `f` returns true, preserving their order.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let len = self.length;
+        // Elements leak instead of being dropped twice if `f` panics
         self.length = 0;
+        let mut deleted = 0;
+        for i in 0..len {
+            unsafe {
+                let p = self.buffer.ptr.add(i);
+                if !f(&*p) {
+                    ptr::drop_in_place(p);
+                    deleted += 1;
+                } else if deleted > 0 {
+                    ptr::copy_nonoverlapping(p, p.sub(deleted), 1);
+                }
+            }
+        }
+        self.length = len - deleted;
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
     }
 
     pub fn is_empty(&self) -> bool {
@@ -115,23 +242,112 @@ impl<T, A: Allocator + Default> CVec<T, A> {
         self.length
     }
 
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.buffer.shrink_to(self.length);
+    }
+
     pub fn as_ptr(&self) -> *const T {
         self.buffer.ptr
     }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.buffer.ptr
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.data_ptr(), self.length) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.data_ptr(), self.length) }
+    }
+
+    // Slices can't be built from a null pointer, even empty ones
+    fn data_ptr(&self) -> *mut T {
+        if self.buffer.ptr.is_null() {
+            NonNull::dangling().as_ptr()
+        } else {
+            self.buffer.ptr
+        }
+    }
+}
+
+impl<T: Clone, A: Allocator> CVec<T, A> {
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        self.reserve(other.len());
+        for value in other {
+            self.push(value.clone());
+        }
+    }
+}
+
+impl<T, A: Allocator> Drop for CVec<T, A> {
+    fn drop(&mut self) {
+        // RawVec frees the memory
+        self.clear();
+    }
 }
 
-impl<T, A: Allocator> Index<usize> for CVec<T, A> {
-    type Output = T;
+impl<T, A: Allocator> Deref for CVec<T, A> {
+    type Target = [T];
 
-    fn index(&self, i: usize) -> &Self::Output {
-        unsafe { self.buffer.ptr.offset(i as isize).as_ref().expect("Null pointer") }
+    fn deref(&self) -> &[T] {
+        self.as_slice()
     }
 }
 
-impl<T, A: Allocator> IndexMut<usize> for CVec<T, A> {
+impl<T, A: Allocator> DerefMut for CVec<T, A> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, A: Allocator> Index<I> for CVec<T, A> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, A: Allocator> IndexMut<I> for CVec<T, A> {
+
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for CVec<T, A> {
+    fn clone(&self) -> Self {
+        let mut res = Self::with_capacity_in(self.length, self.buffer.alloc.clone());
+        res.extend_from_slice(self);
+        res
+    }
+}
+
+impl<T, A: Allocator> Extend<T> for CVec<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: PartialEq, A: Allocator> PartialEq for CVec<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, A: Allocator> Eq for CVec<T, A> {}
 
-    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        unsafe { self.buffer.ptr.offset(i as isize).as_mut().expect("Null pointer") }
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for CVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
     }
 }
 
@@ -157,7 +373,7 @@ impl<T, A: Allocator> Iterator for VecIntoIter<T, A>
             unsafe {
                 let index = self.current;
                 self.current += 1;
-                Some(ptr::read(self.inner.buffer.ptr.offset(index as isize)))
+                Some(ptr::read(self.inner.buffer.ptr.add(index)))
             }
         }
     }
@@ -174,7 +390,7 @@ impl<T, A: Allocator> Drop for VecIntoIter<T, A>
             unsafe {
                 for i in self.current..self.size
                 {
-                    ptr::drop_in_place(self.inner.buffer.ptr.offset(i as isize));
+                    ptr::drop_in_place(self.inner.buffer.ptr.add(i));
                 }
             }
         }
@@ -199,56 +415,37 @@ impl<T, A: Allocator> IntoIterator for CVec<T, A>
     }
 }
 
-pub struct RefVecIntoIter<'a, T, A: Allocator>
-{
-    inner: &'a CVec<T, A>,
-    current: usize,
-    size: usize,
-}
-
-impl<'a, T, A: Allocator> Iterator for RefVecIntoIter<'a, T, A>
+impl<'a, T, A: Allocator> IntoIterator for &'a CVec<T, A>
 {
-    type Item = T;
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
 
-    fn next(&mut self) -> Option<T>
+    fn into_iter(self) -> Self::IntoIter
     {
-        if self.current >= self.size
-        {
-            None
-        }
-        else
-        {
-            unsafe {
-                let index = self.current;
-                self.current += 1;
-                Some(ptr::read(self.inner.buffer.ptr.offset(index as isize)))
-            }
-        }
+        self.iter()
     }
 }
 
-impl<'a, T, A: Allocator> IntoIterator for &'a CVec<T, A>
+impl<'a, T, A: Allocator> IntoIterator for &'a mut CVec<T, A>
 {
-    type Item = T;
-    type IntoIter = RefVecIntoIter<'a, T, A>;
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter
     {
-        RefVecIntoIter {
-            size: self.length,
-            inner: self,
-            current: 0,
-        }
+        self.iter_mut()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::system::SystemAllocator;
+    use core::cell::Cell;
 
     #[test]
     fn test_vec() {
-        let mut cvec: CVec<usize, Win32HeapAllocator> = CVec::new();
+        let mut cvec: CVec<usize, SystemAllocator> = CVec::new();
         assert_eq!(cvec.length, 0);
         cvec.push(12);
         assert_eq!(cvec.length, 1);
@@ -258,4 +455,135 @@ mod tests {
         cvec[0] = 89;
         assert_eq!(cvec[0], 89);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_vec_grow_shrink() {
+        let mut cvec: CVec<usize, SystemAllocator> = CVec::new();
+        for i in 0..1000 {
+            cvec.push(i);
+        }
+        assert!(cvec.capacity() >= 1000);
+        for _ in 0..900 {
+            cvec.pop();
+        }
+        cvec.shrink_to_fit();
+        assert_eq!(cvec.capacity(), 100);
+        for i in 0..100 {
+            assert_eq!(cvec[i], i);
+        }
+        cvec.clear();
+        cvec.shrink_to_fit();
+        assert_eq!(cvec.capacity(), 0);
+        cvec.push(7);
+        assert_eq!(cvec[0], 7);
+    }
+
+    struct DropCounter<'a>(&'a Cell<usize>, u32);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    impl<'a> Clone for DropCounter<'a> {
+        fn clone(&self) -> Self {
+            DropCounter(self.0, self.1)
+        }
+    }
+
+    fn values(cvec: &CVec<DropCounter, SystemAllocator>) -> CVec<u32, SystemAllocator> {
+        let mut res = CVec::new();
+        res.extend(cvec.iter().map(|d| d.1));
+        res
+    }
+
+    #[test]
+    fn test_vec_drop() {
+        let drops = Cell::new(0);
+        {
+            let mut cvec: CVec<DropCounter, SystemAllocator> = CVec::new();
+            for i in 0..10 {
+                cvec.push(DropCounter(&drops, i));
+            }
+            // Iterating by reference must not drop anything
+            for d in &cvec {
+                assert!(d.1 < 10);
+            }
+            assert_eq!(cvec.iter().count(), 10);
+            assert_eq!(drops.get(), 0);
+            cvec.truncate(8);
+            assert_eq!(drops.get(), 2);
+        }
+        assert_eq!(drops.get(), 10);
+
+        let cvec: CVec<DropCounter, SystemAllocator> = (0..4).fold(CVec::new(), |mut v, i| {
+            v.push(DropCounter(&drops, i));
+            v
+        });
+        let mut iter = cvec.into_iter();
+        assert_eq!(iter.next().map(|d| d.1), Some(0));
+        assert_eq!(drops.get(), 11);
+        drop(iter);
+        assert_eq!(drops.get(), 14);
+    }
+
+    #[test]
+    fn test_vec_edit() {
+        let drops = Cell::new(0);
+        let mut cvec: CVec<DropCounter, SystemAllocator> = CVec::with_capacity(4);
+        assert_eq!(cvec.capacity(), 4);
+        for i in 0..6 {
+            cvec.push(DropCounter(&drops, i));
+        }
+        cvec.insert(0, DropCounter(&drops, 10));
+        cvec.insert(7, DropCounter(&drops, 11));
+        cvec.insert(3, DropCounter(&drops, 12));
+        assert_eq!(values(&cvec).as_slice(), &[10, 0, 1, 12, 2, 3, 4, 5, 11]);
+        assert_eq!(cvec.remove(3).1, 12);
+        assert_eq!(cvec.swap_remove(0).1, 10);
+        assert_eq!(cvec.swap_remove(5).1, 4);
+        assert_eq!(cvec.swap_remove(cvec.len() - 1).1, 5);
+        assert_eq!(values(&cvec).as_slice(), &[11, 0, 1, 2, 3]);
+        assert_eq!(drops.get(), 4);
+        cvec.retain(|d| d.1 % 2 == 1);
+        assert_eq!(values(&cvec).as_slice(), &[11, 1, 3]);
+        assert_eq!(drops.get(), 6);
+        for d in &mut cvec {
+            d.1 += 1;
+        }
+        cvec.iter_mut().for_each(|d| d.1 *= 2);
+        assert_eq!(values(&cvec).as_slice(), &[24, 4, 8]);
+        cvec.clear();
+        assert_eq!(drops.get(), 9);
+    }
+
+    #[test]
+    fn test_vec_slice() {
+        let drops = Cell::new(0);
+        let items = [DropCounter(&drops, 1), DropCounter(&drops, 2)];
+        let mut cvec: CVec<DropCounter, SystemAllocator> = CVec::new();
+        cvec.extend_from_slice(&items);
+        cvec.extend_from_slice(&items[..1]);
+        let copy = cvec.clone();
+        drop(cvec);
+        assert_eq!(drops.get(), 3);
+        assert_eq!(values(&copy).as_slice(), &[1, 2, 1]);
+        assert_eq!(copy[1..].len(), 2);
+        assert_eq!(copy.first().map(|d| d.1), Some(1));
+        drop(copy);
+        assert_eq!(drops.get(), 6);
+
+        let empty: CVec<u32, SystemAllocator> = CVec::new();
+        assert_eq!(empty.as_slice(), &[]);
+        assert_eq!(empty.iter().next(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_vec_index_out_of_bounds() {
+        let mut cvec: CVec<u32, SystemAllocator> = CVec::new();
+        cvec.push(1);
+        let _ = cvec[1];
+    }
+}
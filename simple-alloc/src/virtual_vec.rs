@@ -0,0 +1,298 @@
+use crate::pages::{self, PAGE_SIZE};
+use core::cmp::{max, min};
+use core::ffi::c_void;
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut, Index, IndexMut};
+use core::ptr;
+use core::slice::{self, SliceIndex};
+
+/// Vector backed by an address range reserved up front, with pages committed
+/// as it grows. Elements never move, so pointers into it stay valid, which
+/// `CVec` can't offer: JIT code buffers, big sample buffers...
+///
+/// ```ignore
+/// let mut samples: VirtualVec<f32> = VirtualVec::with_max_capacity(44_100 * 60 * 5);
+/// ```
+///
+/// Pushing past the reservation panics, `try_push` hands the value back instead.
+pub struct VirtualVec<T> {
+    ptr: *mut T,
+    length: usize,
+    // Elements fitting in the committed pages
+    capacity: usize,
+    max_capacity: usize,
+}
+
+unsafe impl<T: Send> Send for VirtualVec<T> {}
+unsafe impl<T: Sync> Sync for VirtualVec<T> {}
+
+impl<T> VirtualVec<T> {
+    /// Reserves address space for `max_capacity` elements, no memory is committed yet.
+    pub fn with_max_capacity(max_capacity: usize) -> Self {
+        assert!(mem::size_of::<T>() > 0, "VirtualVec doesn't support zero sized types");
+        assert!(mem::align_of::<T>() <= PAGE_SIZE, "VirtualVec elements can't be aligned above a page");
+        let size = max_capacity.checked_mul(mem::size_of::<T>()).expect("Capacity overflow");
+        let ptr = unsafe { pages::reserve(size).expect("Allocation error") as *mut T };
+        Self {
+            ptr,
+            length: 0,
+            capacity: 0,
+            max_capacity,
+        }
+    }
+
+    fn reserved_bytes(&self) -> usize {
+        pages::page_align(self.max_capacity * mem::size_of::<T>())
+    }
+
+    fn committed_bytes(&self) -> usize {
+        pages::page_align(self.capacity * mem::size_of::<T>())
+    }
+
+    // Commits whole pages, doubling the committed size to keep the number of calls low
+    fn commit(&mut self, needed: usize) -> bool {
+        if needed > self.max_capacity {
+            return false;
+        }
+
+        let committed = self.committed_bytes();
+        let wanted = max(pages::page_align(needed * mem::size_of::<T>()), committed * 2);
+        let new_committed = min(wanted, self.reserved_bytes());
+        unsafe {
+            let start = (self.ptr as *mut u8).add(committed) as *mut c_void;
+            if !pages::commit(start, new_committed - committed) {
+                return false;
+            }
+        }
+        self.capacity = min(new_committed / mem::size_of::<T>(), self.max_capacity);
+        true
+    }
+
+    /// Commits room for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.length.checked_add(additional).expect("Capacity overflow");
+        if needed > self.capacity && !self.commit(needed) {
+            panic!("VirtualVec reservation of {} elements exceeded", self.max_capacity);
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.try_push(value).is_err() {
+            panic!("VirtualVec reservation of {} elements exceeded", self.max_capacity);
+        }
+    }
+
+    /// Hands the value back if the reservation is full.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.length == self.capacity && !self.commit(self.length + 1) {
+            return Err(value);
+        }
+
+        unsafe {
+            self.ptr.add(self.length).write(value);
+        }
+        self.length += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.length == 0 {
+            None
+        } else {
+            self.length -= 1;
+            Some(unsafe { self.ptr.add(self.length).read() })
+        }
+    }
+
+    /// Drops the elements past `len`, the pages stay committed.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.length {
+            return;
+        }
+
+        // Shorten first so a panicking destructor can't cause a double drop
+        let tail = ptr::slice_from_raw_parts_mut(unsafe { self.ptr.add(len) }, self.length - len);
+        self.length = len;
+        unsafe {
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Number of elements fitting in the committed pages.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn max_capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.length) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.length) }
+    }
+}
+
+impl<T: Clone> VirtualVec<T> {
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        self.reserve(other.len());
+        for value in other {
+            self.push(value.clone());
+        }
+    }
+}
+
+impl<T> Drop for VirtualVec<T> {
+    fn drop(&mut self) {
+        self.clear();
+        unsafe {
+            pages::release(self.ptr as *mut c_void, self.reserved_bytes());
+        }
+    }
+}
+
+impl<T> Deref for VirtualVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for VirtualVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> Index<I> for VirtualVec<T> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> IndexMut<I> for VirtualVec<T> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+impl<T> Extend<T> for VirtualVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for VirtualVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a VirtualVec<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut VirtualVec<T> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl<'a> Drop for DropCounter<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_virtual_vec_grow() {
+        // 1 GiB of address space, only the pages written to use memory
+        let mut samples: VirtualVec<f32> = VirtualVec::with_max_capacity(1 << 28);
+        assert_eq!(samples.capacity(), 0);
+        samples.push(0.0);
+        assert_eq!(samples.capacity(), PAGE_SIZE / 4);
+
+        let first = samples.as_ptr();
+        let first_ref: *const f32 = &samples[0];
+        for i in 1..100_000 {
+            samples.push(i as f32);
+        }
+        // Growing never moves the elements
+        assert_eq!(samples.as_ptr(), first);
+        assert_eq!(first_ref, &samples[0] as *const f32);
+        assert_eq!(samples[99_999], 99_999.0);
+        assert!(samples.capacity() < 2 * 100_000 + PAGE_SIZE);
+
+        samples.truncate(10);
+        samples.extend_from_slice(&[1.0, 2.0]);
+        assert_eq!(samples.len(), 12);
+        assert_eq!(samples.pop(), Some(2.0));
+    }
+
+    #[test]
+    fn test_virtual_vec_limit() {
+        let mut code: VirtualVec<[u8; 3]> = VirtualVec::with_max_capacity(2000);
+        code.extend((0..2000).map(|i| [i as u8; 3]));
+        assert_eq!(code.capacity(), 2000);
+        assert_eq!(code.try_push([0xc3; 3]), Err([0xc3; 3]));
+        assert_eq!(code[1999], [207; 3]);
+    }
+
+    #[test]
+    fn test_virtual_vec_drop() {
+        let drops = Cell::new(0);
+        {
+            let mut values = VirtualVec::with_max_capacity(1000);
+            for _ in 0..600 {
+                values.push(DropCounter(&drops));
+            }
+            values.truncate(100);
+            assert_eq!(drops.get(), 500);
+        }
+        assert_eq!(drops.get(), 600);
+    }
+}
@@ -7,12 +7,14 @@
 #[link_args = "/NODEFAULTLIB /SUBSYSTEM:WINDOWS /SAFESEH:NO /DYNAMICBASE:NO /ENTRY:WinMainCRTStartup /LTCG vcruntime.lib"]
 extern "C" {}
 
+extern crate alloc;
+
 use core::intrinsics;
 use core::panic::PanicInfo;
 use core::ptr;
-use win32::{ffi_message_box, MessageBoxW, SwapBuffers, exit_process};
+use win32::{ffi_message_box, MessageBoxW, SwapBuffers, exit_process, wstr};
 use tinygl::*;
-use simplealloc::{WinVec, CString};
+use simplealloc::{WinVec, ArrayString, CStr, GlobalAllocator, Win32HeapAllocator, cstr};
 use neatro::Window;
 
 // These functions and traits are used by the compiler, but not
@@ -26,27 +28,35 @@ fn panic(_info: &PanicInfo) -> ! {
     unsafe { intrinsics::abort() }
 }
 
+#[global_allocator]
+static ALLOCATOR: GlobalAllocator<Win32HeapAllocator> = GlobalAllocator::new();
+
+#[alloc_error_handler]
+fn alloc_error(layout: core::alloc::Layout) -> ! {
+    simplealloc::alloc_error(layout)
+}
+
 
 #[no_mangle]
 pub extern "C" fn WinMainCRTStartup() -> () {
 	WinMain()
 }
 
-const vertex_shader_src: &str = r#"
+const vertex_shader_src: &CStr = cstr!(r#"
     #version 330 core
     layout (location = 0) in vec3 aPos;
     void main()
     {
        gl_Position = vec4(aPos.x, aPos.y, aPos.z, 1.0);
-    }"#;
+    }"#);
 
-const fragment_shader_src: &str = r#"
+const fragment_shader_src: &CStr = cstr!(r#"
     #version 330 core
     out vec4 FragColor;
     void main()
     {
         FragColor = vec4(1, 0, 0, 1);
-    }"#;
+    }"#);
 
 #[no_mangle]
 pub extern "C" fn WinMain() -> () {
@@ -59,8 +69,7 @@ pub extern "C" fn WinMain() -> () {
     real_window.show();
     print_stdout("Import shaders ...\n");
     let vs = glCreateShader(GL_VERTEX_SHADER);
-    let vs_src = CString::from_str(vertex_shader_src).to_i8_str();
-    glShaderSource(vs, 1, &vs_src.as_ptr(), core::ptr::null());
+    glShaderSource(vs, 1, &vertex_shader_src.as_ptr(), core::ptr::null());
     glCompileShader(vs);
     let mut success: i32 = 0;
     glGetShaderiv(vs, GL_COMPILE_STATUS, &mut success);
@@ -68,12 +77,12 @@ pub extern "C" fn WinMain() -> () {
         //ffi_message_box();
         let mut info: [u8;512] = [0;512];
         glGetShaderInfoLog(vs, 512, ptr::null_mut(), &mut info[0]);
-        let info_res = CString::from_u8_slice(&info);
-        let box_title = CString::from_str("Neatro debug").to_u16_str();
+        let info_res: ArrayString<512> = ArrayString::from_u8_slice(&info);
+        let box_title = wstr!("Neatro debug");
         unsafe {
             MessageBoxW(ptr::null_mut(), info_res.to_u16_str().as_ptr(),  box_title.as_ptr(), 0);
         }
-        print_stdout("Shader compilation failed!");
+        cprintln!("Vertex shader {} compilation failed", vs);
     } 
     // else {
     //     print_stdout("Shader compiled");
@@ -83,8 +92,7 @@ pub extern "C" fn WinMain() -> () {
     //     }
     // }
     let fs = glCreateShader(GL_FRAGMENT_SHADER);
-    let fs_src = CString::from_str(fragment_shader_src).to_i8_str();
-    glShaderSource(fs, 1, &fs_src.as_ptr(), core::ptr::null());
+    glShaderSource(fs, 1, &fragment_shader_src.as_ptr(), core::ptr::null());
     glCompileShader(fs);
     let mut success: i32 = 0;
     glGetShaderiv(fs, GL_COMPILE_STATUS, &mut success);
@@ -92,19 +100,19 @@ pub extern "C" fn WinMain() -> () {
         //ffi_message_box();
         let mut info: [u8;512] = [0;512];
         glGetShaderInfoLog(fs, 512, ptr::null_mut(), &mut info[0]);
-        let info_res = CString::from_u8_slice(&info);
-        let box_title = CString::from_str("Neatro debug").to_u16_str();
+        let info_res: ArrayString<512> = ArrayString::from_u8_slice(&info);
+        let box_title = wstr!("Neatro debug");
         unsafe {
             MessageBoxW(ptr::null_mut(), info_res.to_u16_str().as_ptr(),  box_title.as_ptr(), 0);
         }
-        print_stdout("Shader compilation failed!");
+        cprintln!("Fragment shader {} compilation failed", fs);
     }
     let program = glCreateProgram();
     if program == 0 {
-        let info_res = CString::from_str("Program 0");
-        let box_title = CString::from_str("Neatro debug").to_u16_str();
+        let info_res = wstr!("Program 0");
+        let box_title = wstr!("Neatro debug");
         unsafe {
-            MessageBoxW(ptr::null_mut(), info_res.to_u16_str().as_ptr(),  box_title.as_ptr(), 0);
+            MessageBoxW(ptr::null_mut(), info_res.as_ptr(),  box_title.as_ptr(), 0);
         }
     }
     glAttachShader(program, vs);
@@ -115,12 +123,12 @@ pub extern "C" fn WinMain() -> () {
     if success == 0 {
         let mut info: [u8;512] = [0;512];
         glGetProgramInfoLog(program, 512, ptr::null_mut(), &mut info[0]);
-        let info_res = CString::from_u8_slice(&info);
-        let box_title = CString::from_str("Neatro debug").to_u16_str();
+        let info_res: ArrayString<512> = ArrayString::from_u8_slice(&info);
+        let box_title = wstr!("Neatro debug");
         unsafe {
             MessageBoxW(ptr::null_mut(), info_res.to_u16_str().as_ptr(),  box_title.as_ptr(), 0);
         }
-        print_stdout("Shader compilation failed!");
+        cprintln!("Program {} link failed", program);
     }
 
     let vertices: [f32;9] = [
@@ -141,6 +149,7 @@ pub extern "C" fn WinMain() -> () {
     
     glBindBuffer(GL_ARRAY_BUFFER, 0); 
     glBindVertexArray(0);
+    cprintln!("Triangle uploaded: vao {}, vbo {}, GL error {:#x}", vao, vbo, glGetError());
 
     unsafe {glViewport(0, 0, 800, 600);
     glClearColor(0.2, 0.3, 0.3, 1.0);
@@ -14,7 +14,7 @@ pub struct Window {
 impl Window {
     pub fn new_fake(width: usize, height: usize) -> Self {
         let class = WNDCLASSW::new();
-        let window_name = &[b'N' as u16, b'i' as u16, b't' as u16, b'r' as u16, b'o' as u16, 0 as u16];
+        let window_name = wstr!("Nitro");
         let flags: DWORD = 0x00000000 | 0x00C00000 | 0x00080000 | 0x00040000 | 0x00020000 | 0x00010000;
         let dc: HDC;
         let handle: HWND;
@@ -62,7 +62,7 @@ impl Window {
 
     pub fn new_real(width: usize, height: usize) -> Self {
         let class = WNDCLASSW::new();
-        let window_name = &[b'N' as u16, b'i' as u16, b't' as u16, b'r' as u16, b'o' as u16, 0 as u16];
+        let window_name = wstr!("Nitro");
         let flags: DWORD = 0x00000000 | 0x00C00000 | 0x00080000 | 0x00040000 | 0x00020000 | 0x00010000;
         let dc: HDC;
         let handle: HWND;
@@ -1,6 +1,7 @@
 #![no_std]
+use core::fmt::{self, Write};
 use win32::*;
-use simplealloc::{CString, Once};
+use simplealloc::{ArrayString, CStr, Lazy, cstr};
 
 /* GL functions to import:
 glClearColor
@@ -59,43 +60,80 @@ pub type WGLCHOOSEPIXELFORMATARBPROC = extern "system" fn(HDC, *const i32, *cons
 pub type WGLCREATECONTEXTATTRIBSARBPROC = extern "system" fn(HDC, HGLRC, *const i32) -> HGLRC;
 pub type GETERRORPROC = extern "system" fn() -> GLenum;
 
-pub fn get_gl_func_address(func_name: &str) -> win32::FUNCTION_PTR {
-    // let name = &[b'g' as i8, b'l' as i8, b'C' as i8, b'l' as i8, b'e' as i8, b'a' as i8, b'r' as i8, 0 as i8];
-    let name = CString::from_str(func_name).to_i8_str();
+pub fn get_gl_func_address(func_name: &CStr) -> win32::FUNCTION_PTR {
     let mut p;
     unsafe {
-        p = wglGetProcAddress(name.as_ptr());
+        p = wglGetProcAddress(func_name.as_ptr());
         if p.is_null() || p == 1 as win32::FUNCTION_PTR || p == 2 as win32::FUNCTION_PTR || p == 3 as win32::FUNCTION_PTR {
             // let dll = &[b'o' as u16, b'p' as u16, b'e' as u16, b'n' as u16, b'g' as u16, b'l' as u16, b'3' as u16, b'2' as u16, b'.' as u16, b'd' as u16, b'l' as u16, b'l' as u16, 0 as u16]; //"opengl32.dll"
-            let dll = CString::from_str("opengl32.dll\0").to_u16_str();
+            let dll = wstr!("opengl32.dll");
             let module = LoadLibraryW(dll.as_ptr());
-            p = GetProcAddress(module, name.as_ptr());
+            p = GetProcAddress(module, func_name.as_ptr());
         }
     }
     p
 }
 
 pub fn print_stdout(message: &str) {
+    write_stdout(message.as_bytes());
+}
+
+/// Formats into a stack buffer flushed to the console when full, never allocates.
+pub fn print_stdout_fmt(args: fmt::Arguments) {
+    let mut console = Console { buffer: ArrayString::new() };
+    let _ = console.write_fmt(args);
+    write_stdout(console.buffer.as_bytes());
+}
+
+struct Console {
+    buffer: ArrayString<256>,
+}
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.buffer.try_push_str(s).is_err() {
+            write_stdout(self.buffer.as_bytes());
+            self.buffer.clear();
+            // Pieces larger than the buffer go out directly
+            if self.buffer.try_push_str(s).is_err() {
+                write_stdout(s.as_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_stdout(text: &[u8]) {
     let std_out = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
     let mut written: DWORD = 0;
-    let text = CString::from_str(message);
     unsafe { WriteConsoleA(std_out, text.as_ptr(), text.len() as u32, &mut written, core::ptr::null_mut()) };
 }
 
+/// `println!` for the console, goes through `print_stdout_fmt`.
+#[macro_export]
+macro_rules! cprintln {
+    () => {
+        $crate::print_stdout("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::print_stdout_fmt(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
 #[allow(non_snake_case)]
 pub fn glGetString(name: GLenum) -> *const u8 {
     // static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
     // static ONCE: Once = Once::INIT;
     // ONCE.run_once(|| {
-    //     unsafe { FUNC_PTR = get_gl_func_address("glGetString") }
+    //     unsafe { FUNC_PTR = get_gl_func_address(cstr!("glGetString")) }
     // });
     // unsafe { core::mem::transmute::<_, GETSTRINGPROC>(FUNC_PTR) (name) }
     unsafe {
         print_stdout("glGetString before GET FUNC PTR\n");
-        let func_ptr = get_gl_func_address("glGetString");
+        let func_ptr = get_gl_func_address(cstr!("glGetString"));
         print_stdout("glGetString after GET FUNC PTR\n");
         if func_ptr.is_null() {
-            print_stdout("glGetString NULL\n");
+            cprintln!("glGetString NULL (name {:#x})", name);
         }
         core::mem::transmute::<_, GETSTRINGPROC>(func_ptr) (name)
     }
@@ -103,255 +141,205 @@ pub fn glGetString(name: GLenum) -> *const u8 {
 
 #[allow(non_snake_case)]
 pub fn glCreateShader(shader_type: u32) -> u32 {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
+    static FUNC: Lazy<Option<CREATESHADERPROC>> = Lazy::new(|| {
         print_stdout("glCreateShader GET FUNC PTR\n");
-        unsafe { FUNC_PTR = get_gl_func_address("glCreateShader") }
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glCreateShader"))) }
     });
-    unsafe { core::mem::transmute::<_, CREATESHADERPROC>(FUNC_PTR) (shader_type) }
+    FUNC.expect("glCreateShader not found")(shader_type)
 }
 
 #[allow(non_snake_case)]
 pub fn glShaderSource(shader: GLuint, count: GLsizei, string: *const *const GLchar, length: *const GLint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
+    static FUNC: Lazy<Option<SHADERSOURCEPROC>> = Lazy::new(|| {
         print_stdout("glShaderSource GET FUNC PTR\n");
-        unsafe { FUNC_PTR = get_gl_func_address("glShaderSource") }
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glShaderSource"))) }
     });
-    unsafe { core::mem::transmute::<_, SHADERSOURCEPROC>(FUNC_PTR) (shader, count, string, length); }
+    FUNC.expect("glShaderSource not found")(shader, count, string, length);
 }
 
 #[allow(non_snake_case)]
 pub fn glCompileShader(shader: GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glCompileShader") }
+    static FUNC: Lazy<Option<COMPILESHADERPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glCompileShader"))) }
     });
-    print_stdout("glCompileShader\n");
-    unsafe { core::mem::transmute::<_, COMPILESHADERPROC>(FUNC_PTR) (shader); }
+    cprintln!("glCompileShader {}", shader);
+    FUNC.expect("glCompileShader not found")(shader);
 }
 
 #[allow(non_snake_case)]
 pub fn glGetShaderiv(shader: u32, param_name: GLenum, params: *mut GLint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glGetShaderiv\0") }
+    static FUNC: Lazy<Option<GETSHADERIVPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glGetShaderiv"))) }
     });
-    unsafe { core::mem::transmute::<_, GETSHADERIVPROC>(FUNC_PTR) (shader, param_name, params); }
+    FUNC.expect("glGetShaderiv not found")(shader, param_name, params);
 }
 
 #[allow(non_snake_case)]
 pub fn glGetShaderInfoLog(shader: u32, max_length: i32, length: *mut i32, info_log: *mut u8) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glGetShaderInfoLog\0") }
+    static FUNC: Lazy<Option<GETSHADERINFOLOGPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glGetShaderInfoLog"))) }
     });
-    unsafe { core::mem::transmute::<_, GETSHADERINFOLOGPROC>(FUNC_PTR) (shader, max_length, length, info_log); }
+    FUNC.expect("glGetShaderInfoLog not found")(shader, max_length, length, info_log);
 }
 
 #[allow(non_snake_case)]
 pub fn glAttachShader(program: GLuint, shader: GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glAttachShader\0") }
+    static FUNC: Lazy<Option<ATTACHSHADERPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glAttachShader"))) }
     });
-    unsafe { core::mem::transmute::<_, ATTACHSHADERPROC>(FUNC_PTR) (program, shader); }
+    FUNC.expect("glAttachShader not found")(program, shader);
 }
 
 #[allow(non_snake_case)]
 pub fn glCreateProgram() -> u32 {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glCreateProgram") }
+    static FUNC: Lazy<Option<CREATEPROGRAMPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glCreateProgram"))) }
     });
-    unsafe { core::mem::transmute::<_, CREATEPROGRAMPROC>(FUNC_PTR) () }
+    FUNC.expect("glCreateProgram not found")()
 }
 
 #[allow(non_snake_case)]
 pub fn glLinkProgram(program: GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glLinkProgram\0") }
+    static FUNC: Lazy<Option<LINKPROGRAMPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glLinkProgram"))) }
     });
-    unsafe { core::mem::transmute::<_, LINKPROGRAMPROC>(FUNC_PTR) (program); }
+    FUNC.expect("glLinkProgram not found")(program);
 }
 
 
 pub fn glGetProgramiv(program: GLuint, p_name: GLenum, params: *mut GLint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glGetProgramiv") }
+    static FUNC: Lazy<Option<GETPROGRAMIVPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glGetProgramiv"))) }
     });
-    unsafe { core::mem::transmute::<_, GETPROGRAMIVPROC>(FUNC_PTR) (program, p_name, params); }
+    FUNC.expect("glGetProgramiv not found")(program, p_name, params);
 }
 
 #[allow(non_snake_case)]
 pub fn glGetProgramInfoLog(program: u32, max_length: i32, length: *mut i32, info_log: *mut u8) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glGetProgramInfoLog") }
+    static FUNC: Lazy<Option<GETPROGRAMINFOLOGPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glGetProgramInfoLog"))) }
     });
-    unsafe { core::mem::transmute::<_, GETPROGRAMINFOLOGPROC>(FUNC_PTR) (program, max_length, length, info_log); }
+    FUNC.expect("glGetProgramInfoLog not found")(program, max_length, length, info_log);
 }
 
 #[allow(non_snake_case)]
 pub fn glDeleteShader(shader: GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glLinkProgram\0") }
+    static FUNC: Lazy<Option<DELETESHADERPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glDeleteShader"))) }
     });
-    unsafe { core::mem::transmute::<_, DELETESHADERPROC>(FUNC_PTR) (shader); }
+    FUNC.expect("glDeleteShader not found")(shader);
 }
 
 #[allow(non_snake_case)]
 pub fn glGenVertexArrays(n: GLsizei, arrays: *mut GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glGenVertexArrays\0") }
+    static FUNC: Lazy<Option<GENVERTEXARRAYSPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glGenVertexArrays"))) }
     });
-    unsafe { core::mem::transmute::<_, GENVERTEXARRAYSPROC>(FUNC_PTR) (n, arrays); }
+    FUNC.expect("glGenVertexArrays not found")(n, arrays);
 }
 
 #[allow(non_snake_case)]
 pub fn glGenBuffers(n: GLsizei, buffers: *mut GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glGenBuffers\0") }
+    static FUNC: Lazy<Option<GENBUFFERSPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glGenBuffers"))) }
     });
-    unsafe { core::mem::transmute::<_, GENBUFFERSPROC>(FUNC_PTR) (n, buffers); }
+    FUNC.expect("glGenBuffers not found")(n, buffers);
 }
 
 #[allow(non_snake_case)]
 pub fn glBindBuffer(target: GLenum, buffer: GLuint) -> () {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glBindBuffer\0") }
+    static FUNC: Lazy<Option<BINDBUFFERPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glBindBuffer"))) }
     });
-    unsafe { core::mem::transmute::<_, BINDBUFFERPROC>(FUNC_PTR) (target, buffer); }
+    FUNC.expect("glBindBuffer not found")(target, buffer);
 }
 
 #[allow(non_snake_case)]
 pub fn glBindVertexArray(array: GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glBindVertexArray\0") }
+    static FUNC: Lazy<Option<BINDVERTEXARRAYPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glBindVertexArray"))) }
     });
-    unsafe { core::mem::transmute::<_, BINDVERTEXARRAYPROC>(FUNC_PTR) (array); }
+    FUNC.expect("glBindVertexArray not found")(array);
 }
 
 #[allow(non_snake_case)]
 pub fn glBufferData(target: GLenum, size: GLsizeiptr, data: *const GLvoid, usage: GLenum) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glBufferData\0") }
+    static FUNC: Lazy<Option<BUFFERDATAPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glBufferData"))) }
     });
-    unsafe { core::mem::transmute::<_, BUFFERDATAPROC>(FUNC_PTR) (target, size, data, usage); }
+    FUNC.expect("glBufferData not found")(target, size, data, usage);
 }
 
 #[allow(non_snake_case)]
 pub fn glVertexAttribPointer(index: GLuint, size: GLint, kind: GLenum, normalized: GLboolean, stride: GLsizei, pointer: *const GLvoid) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glVertexAttribPointer\0") }
+    static FUNC: Lazy<Option<VERTEXATTRIBPOINTERPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glVertexAttribPointer"))) }
     });
-    unsafe { core::mem::transmute::<_, VERTEXATTRIBPOINTERPROC>(FUNC_PTR) (index, size, kind, normalized, stride, pointer); }
+    FUNC.expect("glVertexAttribPointer not found")(index, size, kind, normalized, stride, pointer);
 }
 
 #[allow(non_snake_case)]
 pub fn glEnableVertexAttribArray(index: GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glEnableVertexAttribArray\0") }
+    static FUNC: Lazy<Option<ENABLEVERTEXATTRIBARRAYPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glEnableVertexAttribArray"))) }
     });
-    unsafe { core::mem::transmute::<_, ENABLEVERTEXATTRIBARRAYPROC>(FUNC_PTR) (index); }
+    FUNC.expect("glEnableVertexAttribArray not found")(index);
 }
 
 #[allow(non_snake_case)]
 pub fn glUseProgram(program: GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glUseProgram\0") }
+    static FUNC: Lazy<Option<USEPROGRAMPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glUseProgram"))) }
     });
-    unsafe { core::mem::transmute::<_, USEPROGRAMPROC>(FUNC_PTR) (program); }
+    FUNC.expect("glUseProgram not found")(program);
 }
 
 #[allow(non_snake_case)]
 pub fn glDeleteVertexArrays(n: GLsizei, arrays: *const GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glDeleteVertexArrays\0") }
+    static FUNC: Lazy<Option<DELETEVERTEXARRAYSPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glDeleteVertexArrays"))) }
     });
-    unsafe { core::mem::transmute::<_, DELETEVERTEXARRAYSPROC>(FUNC_PTR) (n, arrays); }
+    FUNC.expect("glDeleteVertexArrays not found")(n, arrays);
 }
 
 #[allow(non_snake_case)]
 pub fn glDeleteBuffers(n: GLsizei, buffers: *const GLuint) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glDeleteBuffers\0") }
+    static FUNC: Lazy<Option<DELETEBUFFERSPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glDeleteBuffers"))) }
     });
-    unsafe { core::mem::transmute::<_, DELETEBUFFERSPROC>(FUNC_PTR) (n, buffers); }
+    FUNC.expect("glDeleteBuffers not found")(n, buffers);
 }
 
 #[allow(non_snake_case)]
 pub fn glClear2(mask: u32) {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glClear\0") }
+    static FUNC: Lazy<Option<CLEARPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glClear"))) }
     });
-    unsafe { core::mem::transmute::<_, CLEARPROC>(FUNC_PTR) (mask); }
+    FUNC.expect("glClear not found")(mask);
 }
 
 #[allow(non_snake_case)]
 pub fn wglChoosePixelFormatARB(hdc: HDC, piAttribIList: *const i32, pfAttribFList: *const f32, nMaxFormats: u32, piFormats: *mut i32, nNumFormats: *mut u32) -> bool {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("wglChoosePixelFormatARB\0") }
+    static FUNC: Lazy<Option<WGLCHOOSEPIXELFORMATARBPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("wglChoosePixelFormatARB"))) }
     });
-    unsafe { core::mem::transmute::<_, WGLCHOOSEPIXELFORMATARBPROC>(FUNC_PTR) (hdc, piAttribIList, pfAttribFList, nMaxFormats, piFormats, nNumFormats) }
+    FUNC.expect("wglChoosePixelFormatARB not found")(hdc, piAttribIList, pfAttribFList, nMaxFormats, piFormats, nNumFormats)
 }
 
 #[allow(non_snake_case)]
 pub fn wglCreateContextAttribsARB(hdc: HDC, hglrc: HGLRC, attribList: *const i32) -> HGLRC {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("wglCreateContextAttribsARB\0") }
+    static FUNC: Lazy<Option<WGLCREATECONTEXTATTRIBSARBPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("wglCreateContextAttribsARB"))) }
     });
-    unsafe { core::mem::transmute::<_, WGLCREATECONTEXTATTRIBSARBPROC>(FUNC_PTR) (hdc, hglrc, attribList) }
+    FUNC.expect("wglCreateContextAttribsARB not found")(hdc, hglrc, attribList)
 }
 
 #[allow(non_snake_case)]
 pub fn glGetError() -> GLenum {
-    static mut FUNC_PTR: win32::FUNCTION_PTR = core::ptr::null_mut();
-    static ONCE: Once = Once::INIT;
-    ONCE.run_once(|| {
-        unsafe { FUNC_PTR = get_gl_func_address("glGetError") }
+    static FUNC: Lazy<Option<GETERRORPROC>> = Lazy::new(|| {
+        unsafe { core::mem::transmute(get_gl_func_address(cstr!("glGetError"))) }
     });
-    unsafe { core::mem::transmute::<_, GETERRORPROC>(FUNC_PTR) () }
+    FUNC.expect("glGetError not found")()
 }
 
 #[link(name = "Opengl32")]
@@ -1,6 +1,9 @@
 #![no_std]
 use core::ptr;
 
+#[macro_use]
+pub mod wide;
+
 pub enum HWND_ {}
 pub enum HANDLE_ {}
 pub enum HMENU_ {}
@@ -52,7 +55,7 @@ pub struct WNDCLASSW {
 
 impl WNDCLASSW {
     pub fn new() -> Self {
-        let class_name = &[b'n' as u16, b'i' as u16, b't' as u16, b'r' as u16, b'o' as u16, b'_' as u16, b'w' as u16, b'n' as u16, b'd' as u16, 0 as u16];
+        let class_name = wstr!("nitro_wnd");
         Self {
             style : 0x0002 | 0x0001 | 0x0020,
             lpfnWndProc: Some(wnd_proc),
@@ -263,6 +266,13 @@ extern "stdcall" {
         lpMem: LPVOID
     ) -> i32;
 
+    pub fn HeapReAlloc(
+        hHeap: HANDLE,
+        dwFlags: DWORD,
+        lpMem: LPVOID,
+        dwBytes: usize
+    ) -> LPVOID;
+
     pub fn GetProcAddress(
         hModule: HMODULE, 
         lpProcName: LPCSTR
@@ -295,6 +305,7 @@ extern "stdcall" {
     pub fn GetStdHandle(nStdHandle: DWORD) -> HANDLE;
     pub fn WriteConsoleA(hConsoleOutput: HANDLE, lpBuffer: *const u8, nNumberOfCharsToWrite: DWORD, lpNumberOfCharsWritten: *mut DWORD, lpReserved: LPVOID) -> bool;
     pub fn GetLastError() -> DWORD;
+    pub fn SwitchToThread() -> i32;
 }
 
 #[link(name = "Gdi32")]
@@ -324,7 +335,7 @@ pub fn get_module_handle() -> HINSTANCE {
 }
 
 pub fn ffi_message_box() {
-    let title_u16 = &[b'N' as u16, b'i' as u16, b't' as u16, b'r' as u16, b'o' as u16, 0 as u16];
+    let title_u16 = wstr!("Nitro");
     unsafe {
         MessageBoxW(ptr::null_mut(), ptr::null_mut(),  title_u16.as_ptr(), 0);
     }
@@ -366,7 +377,7 @@ pub struct Window {
 impl Window {
     pub fn new(width: usize, height: usize) -> Self {
         let class = WNDCLASSW::new();
-        let window_name = &[b'N' as u16, b'i' as u16, b't' as u16, b'r' as u16, b'o' as u16, 0 as u16];
+        let window_name = wstr!("Nitro");
         let flags: DWORD = 0x00000000 | 0x00C00000 | 0x00080000 | 0x00040000 | 0x00020000 | 0x00010000;
         let dc: HDC;
         let handle: HWND;
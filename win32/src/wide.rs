@@ -0,0 +1,94 @@
+// Compile-time UTF-8 -> UTF-16 conversion backing the `wstr!` macro
+
+/// Number of UTF-16 units needed to encode `s`, without the terminating NUL.
+pub const fn utf16_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    let mut len = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b < 0x80 {
+            i += 1;
+            len += 1;
+        } else if b < 0xE0 {
+            i += 2;
+            len += 1;
+        } else if b < 0xF0 {
+            i += 3;
+            len += 1;
+        } else {
+            // Outside the BMP, needs a surrogate pair
+            i += 4;
+            len += 2;
+        }
+    }
+    len
+}
+
+/// Encodes `s` as NUL-terminated UTF-16, `N` must be `utf16_len(s) + 1`.
+pub const fn encode_utf16<const N: usize>(s: &str) -> [u16; N] {
+    assert!(N == utf16_len(s) + 1, "Wrong buffer length for the wide string");
+    let bytes = s.as_bytes();
+    let mut res = [0u16; N];
+    let mut i = 0;
+    let mut j = 0;
+    while i < bytes.len() {
+        let b = bytes[i] as u32;
+        let c;
+        if b < 0x80 {
+            c = b;
+            i += 1;
+        } else if b < 0xE0 {
+            c = (b & 0x1F) << 6 | (bytes[i + 1] as u32 & 0x3F);
+            i += 2;
+        } else if b < 0xF0 {
+            c = (b & 0x0F) << 12 | (bytes[i + 1] as u32 & 0x3F) << 6 | (bytes[i + 2] as u32 & 0x3F);
+            i += 3;
+        } else {
+            c = (b & 0x07) << 18
+                | (bytes[i + 1] as u32 & 0x3F) << 12
+                | (bytes[i + 2] as u32 & 0x3F) << 6
+                | (bytes[i + 3] as u32 & 0x3F);
+            i += 4;
+        }
+
+        assert!(c != 0, "Wide strings can't contain NUL characters");
+        if c >= 0x10000 {
+            let c = c - 0x10000;
+            res[j] = 0xD800 | (c >> 10) as u16;
+            res[j + 1] = 0xDC00 | (c & 0x3FF) as u16;
+            j += 2;
+        } else {
+            res[j] = c as u16;
+            j += 1;
+        }
+    }
+    res
+}
+
+/// NUL-terminated UTF-16 string literal, encoded at compile time:
+///
+/// ```ignore
+/// let title: &'static [u16] = wstr!("Nitro");
+/// MessageBoxW(ptr::null_mut(), ptr::null_mut(), title.as_ptr(), 0);
+/// ```
+#[macro_export]
+macro_rules! wstr {
+    ($s:expr) => {{
+        const UTF8: &str = $s;
+        const WIDE: [u16; $crate::wide::utf16_len(UTF8) + 1] = $crate::wide::encode_utf16(UTF8);
+        &WIDE as &'static [u16]
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_wstr() {
+        let ascii = wstr!("nitro_wnd");
+        assert_eq!(ascii, &[b'n' as u16, b'i' as u16, b't' as u16, b'r' as u16, b'o' as u16, b'_' as u16, b'w' as u16, b'n' as u16, b'd' as u16, 0]);
+        assert_eq!(wstr!(""), &[0]);
+        assert_eq!(wstr!("Démo ✓"), &[0x44, 0xE9, 0x6D, 0x6F, 0x20, 0x2713, 0]);
+        assert_eq!(wstr!("🎵"), &[0xD83C, 0xDFB5, 0]);
+    }
+}